@@ -0,0 +1,210 @@
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::HashMap;
+
+pub struct SpriteAnimationPlugin;
+
+impl Plugin for SpriteAnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<AnimationClip>()
+            .add_event::<AnimationEvent>()
+            .add_system(animate_sprites.label("animate_sprites"));
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PlaybackMode {
+    Loop,
+    //Stops on the last frame
+    Once,
+    //Plays forwards then backwards, good for 3 frame walk cycles
+    PingPong,
+}
+
+#[derive(TypeUuid, Clone)]
+#[uuid = "6b1f3c8e-2f4a-4d35-9a47-52c0e5a1d9b3"]
+pub struct AnimationClip {
+    //Indices into whatever texture atlas the sprite uses
+    pub frames: Vec<usize>,
+    //Seconds each frame is shown for
+    pub durations: Vec<f32>,
+    pub mode: PlaybackMode,
+    //Sends an AnimationEvent with the name whenever the frame is shown
+    pub events: Vec<(usize, String)>,
+}
+
+impl AnimationClip {
+    //Every frame shown for the same time
+    pub fn new(frames: Vec<usize>, frame_time: f32, mode: PlaybackMode) -> AnimationClip {
+        let durations = vec![frame_time; frames.len()];
+        AnimationClip::with_durations(frames, durations, mode)
+    }
+
+    pub fn with_durations(
+        frames: Vec<usize>,
+        durations: Vec<f32>,
+        mode: PlaybackMode,
+    ) -> AnimationClip {
+        assert!(!frames.is_empty());
+        assert_eq!(frames.len(), durations.len());
+        //Zero length frames would never finish advancing
+        assert!(durations.iter().all(|duration| *duration > 0.0));
+        AnimationClip {
+            frames: frames,
+            durations: durations,
+            mode: mode,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn with_event(mut self, frame: usize, name: &str) -> AnimationClip {
+        assert!(frame < self.frames.len());
+        self.events.push((frame, name.to_string()));
+        self
+    }
+}
+
+pub struct AnimationEvent {
+    pub entity: Entity,
+    pub name: String,
+}
+
+//Plays named clips on an entity's TextureAtlasSprite
+#[derive(Component)]
+pub struct Animator {
+    clips: HashMap<String, Handle<AnimationClip>>,
+    current: String,
+    frame: usize,
+    elapsed: f32,
+    //Direction for PingPong clips
+    reverse: bool,
+    finished: bool,
+    //Events for the first frame still need to be sent
+    just_started: bool,
+}
+
+impl Animator {
+    pub fn new(clips: HashMap<String, Handle<AnimationClip>>, start: &str) -> Animator {
+        assert!(clips.contains_key(start), "No clip named {}", start);
+        Animator {
+            clips: clips,
+            current: start.to_string(),
+            frame: 0,
+            elapsed: 0.0,
+            reverse: false,
+            finished: false,
+            just_started: true,
+        }
+    }
+
+    //Restarts only if a different clip was playing
+    pub fn play(&mut self, name: &str) {
+        if self.current == name {
+            return;
+        }
+        assert!(self.clips.contains_key(name), "No clip named {}", name);
+        self.current = name.to_string();
+        self.frame = 0;
+        self.elapsed = 0.0;
+        self.reverse = false;
+        self.finished = false;
+        self.just_started = true;
+    }
+
+    pub fn is_playing(&self, name: &str) -> bool {
+        self.current == name
+    }
+
+    //Only ever true for Once clips
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    fn advance(&mut self, clip: &AnimationClip) {
+        let last = clip.frames.len() - 1;
+        match clip.mode {
+            PlaybackMode::Loop => {
+                self.frame = if self.frame == last {
+                    0
+                } else {
+                    self.frame + 1
+                };
+            }
+            PlaybackMode::Once => {
+                if self.frame == last {
+                    self.finished = true;
+                } else {
+                    self.frame += 1;
+                }
+            }
+            PlaybackMode::PingPong => {
+                if last == 0 {
+                    return;
+                }
+                if self.frame == last {
+                    self.reverse = true;
+                } else if self.frame == 0 {
+                    self.reverse = false;
+                }
+                if self.reverse {
+                    self.frame -= 1;
+                } else {
+                    self.frame += 1;
+                }
+            }
+        }
+    }
+}
+
+fn send_frame_events(
+    events: &mut EventWriter<AnimationEvent>,
+    entity: Entity,
+    clip: &AnimationClip,
+    frame: usize,
+) {
+    for (_, name) in clip
+        .events
+        .iter()
+        .filter(|(event_frame, _)| *event_frame == frame)
+    {
+        events.send(AnimationEvent {
+            entity: entity,
+            name: name.clone(),
+        });
+    }
+}
+
+fn animate_sprites(
+    mut animator_query: Query<(Entity, &mut Animator, &mut TextureAtlasSprite)>,
+    clips: Res<Assets<AnimationClip>>,
+    time: Res<Time>,
+    mut events: EventWriter<AnimationEvent>,
+) {
+    for (entity, mut animator, mut sprite) in animator_query.iter_mut() {
+        let clip = match animator
+            .clips
+            .get(&animator.current)
+            .and_then(|handle| clips.get(handle))
+        {
+            Some(clip) => clip,
+            None => continue,
+        };
+
+        if animator.just_started {
+            animator.just_started = false;
+            send_frame_events(&mut events, entity, clip, animator.frame);
+        }
+
+        animator.elapsed += time.delta_seconds();
+        while !animator.finished && animator.elapsed >= clip.durations[animator.frame] {
+            animator.elapsed -= clip.durations[animator.frame];
+            let previous = animator.frame;
+            animator.advance(clip);
+            if animator.frame != previous {
+                send_frame_events(&mut events, entity, clip, animator.frame);
+            }
+        }
+
+        sprite.index = clip.frames[animator.frame];
+    }
+}
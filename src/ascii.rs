@@ -1,21 +1,77 @@
+use crate::font::{load_fonts, BitmapFont, FontId, Fonts, GlyphInfo};
 use crate::TILE_SIZE;
 use bevy::prelude::*;
+use bevy::transform::TransformSystem;
 
 #[derive(Clone)]
 pub struct AsciiSheet(pub Handle<TextureAtlas>);
 
+//Change any field and the glyphs on screen get updated to match
+//Hold onto the entity from spawn_ascii_text to change it later
 #[derive(Component)]
-pub struct AsciiText {
-    //TODO: Magic value for identitfying which text is which, needs more api work
-    // 0 means dont care
-    pub id: usize,
+pub struct TextWidget {
+    pub text: String,
+    //Used wherever the markup doesn't pick a colour
+    pub color: Color,
+    pub layout: TextLayout,
 }
 
+//What's currently on screen for a TextWidget, one entry per glyph child
+#[derive(Component)]
+struct TextGlyphs {
+    sprites: Vec<Entity>,
+    shown: Vec<Glyph>,
+}
+
+#[derive(Clone, PartialEq)]
+struct Glyph {
+    //Characters a font doesn't have come from the default font's atlas
+    atlas: Handle<TextureAtlas>,
+    index: usize,
+    size: Vec2,
+    color: Color,
+    position: Vec3,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TextAlign {
+    //Text position is half a tile in from the left of each line
+    //Same as the center of the first character in the default font
+    Left,
+    Center,
+    //Text position is half a tile in from the right of each line
+    Right,
+}
+
+impl Default for TextAlign {
+    fn default() -> Self {
+        TextAlign::Left
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct TextLayout {
+    //In tiles, words move to the next line instead of going past it
+    //Tiles are characters in the default font
+    pub max_width: Option<usize>,
+    pub align: TextAlign,
+    pub font: FontId,
+}
+
+//Drawn for anything not on the sheet
+const MISSING_CHAR: char = '?';
+
 pub struct AsciiPlugin;
 
 impl Plugin for AsciiPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system_to_stage(StartupStage::PreStartup, load_ascii);
+        app.add_asset::<BitmapFont>()
+            .add_startup_system_to_stage(StartupStage::PreStartup, load_ascii)
+            //After transforms so new glyphs can be placed right away instead of flashing at the origin
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                update_text_widgets.after(TransformSystem::TransformPropagate),
+            );
     }
 }
 
@@ -45,93 +101,417 @@ pub fn spawn_ascii_sprite(
         .id()
 }
 
+//The ascii sheet is the default font's atlas, sprites index it by character code
 fn load_ascii(
     mut commands: Commands,
     assets: Res<AssetServer>,
     mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut font_assets: ResMut<Assets<BitmapFont>>,
 ) {
-    let texture_handle = assets.load("Ascii.png");
-    let texture_atlas = TextureAtlas::from_grid_with_padding(
-        texture_handle,
-        Vec2::splat(9.0),
-        16,
-        16,
-        Vec2::splat(2.0),
-    );
-    let atlas_handle = texture_atlases.add(texture_atlas);
-    commands.insert_resource(AsciiSheet(atlas_handle));
-}
-
-pub fn update_ascii_text(
-    commands: &mut Commands,
-    to_update: (Entity, &Children),
-    ascii: AsciiSheet,
+    let fonts = load_fonts(&assets, &mut texture_atlases, &mut font_assets);
+    let ascii = font_assets.get(fonts.get(FontId::Ascii)).unwrap();
+    commands.insert_resource(AsciiSheet(ascii.atlas.clone()));
+    commands.insert_resource(fonts);
+}
+
+//Only touches glyphs that actually changed instead of respawning the whole string
+//Glyphs get their GlobalTransform set here too since propagation already ran this frame
+fn update_text_widgets(
+    mut commands: Commands,
+    mut text_query: Query<
+        (Entity, &TextWidget, &mut TextGlyphs, &GlobalTransform),
+        Changed<TextWidget>,
+    >,
+    mut sprite_query: Query<
+        (
+            &mut TextureAtlasSprite,
+            &mut Handle<TextureAtlas>,
+            &mut Transform,
+            &mut GlobalTransform,
+        ),
+        Without<TextWidget>,
+    >,
+    fonts: Res<Fonts>,
+    font_assets: Res<Assets<BitmapFont>>,
+) {
+    for (entity, widget, mut glyphs, parent_transform) in text_query.iter_mut() {
+        let new_glyphs = layout_glyphs(
+            &widget.text,
+            widget.color,
+            widget.layout,
+            &fonts,
+            &font_assets,
+        );
+        if new_glyphs == glyphs.shown {
+            continue;
+        }
+
+        let kept = glyphs.sprites.len().min(new_glyphs.len());
+        for i in 0..kept {
+            if glyphs.shown[i] == new_glyphs[i] {
+                continue;
+            }
+            if let Ok((mut sprite, mut atlas, mut transform, mut global)) =
+                sprite_query.get_mut(glyphs.sprites[i])
+            {
+                let glyph = &new_glyphs[i];
+                sprite.index = glyph.index;
+                sprite.color = glyph.color;
+                sprite.custom_size = Some(glyph.size);
+                *atlas = glyph.atlas.clone();
+                transform.translation = glyph.position;
+                *global = parent_transform.mul_transform(*transform);
+            }
+        }
+
+        for sprite in glyphs.sprites.drain(kept..) {
+            commands.entity(sprite).despawn_recursive();
+        }
+        let added: Vec<Entity> = new_glyphs[kept..]
+            .iter()
+            .map(|glyph| spawn_glyph(&mut commands, glyph, parent_transform))
+            .collect();
+        commands.entity(entity).push_children(&added);
+        glyphs.sprites.extend(added);
+
+        glyphs.shown = new_glyphs;
+        commands
+            .entity(entity)
+            .insert(Name::new(format!("Text - {}", widget.text)));
+    }
+}
+
+fn markup_color(name: &str) -> Option<Color> {
+    match name {
+        "white" => Some(Color::WHITE),
+        "gray" => Some(Color::rgb(0.5, 0.5, 0.5)),
+        "red" => Some(Color::RED),
+        "green" => Some(Color::GREEN),
+        "blue" => Some(Color::rgb(0.3, 0.5, 1.0)),
+        "yellow" => Some(Color::YELLOW),
+        "orange" => Some(Color::ORANGE),
+        _ => name.strip_prefix('#').and_then(|hex| Color::hex(hex).ok()),
+    }
+}
+
+//Closest character the sheet has, the sheet is laid out like latin-1
+fn substitute_char(char: char) -> char {
+    if char as usize <= 255 {
+        return char;
+    }
+    match char {
+        '\u{2018}' | '\u{2019}' => '\'',
+        '\u{201C}' | '\u{201D}' => '"',
+        '\u{2013}' | '\u{2014}' => '-',
+        '\u{2026}' => '.',
+        _ => MISSING_CHAR,
+    }
+}
+
+//Splits markup into coloured characters
+//{red} switches colour until {/}, {#ff8800} takes hex and {{ is a literal {
+//Unknown tags are printed as they are
+fn parse_markup(to_print: &str, default_color: Color) -> Vec<(char, Color)> {
+    let mut glyphs = Vec::new();
+    let mut color = default_color;
+    let mut rest = to_print;
+    while let Some(char) = rest.chars().next() {
+        if char == '{' {
+            if let Some(after) = rest.strip_prefix("{{") {
+                glyphs.push(('{', color));
+                rest = after;
+                continue;
+            }
+            if let Some(end) = rest.find('}') {
+                let tag = &rest[1..end];
+                let new_color = if tag == "/" {
+                    Some(default_color)
+                } else {
+                    markup_color(tag)
+                };
+                if let Some(new_color) = new_color {
+                    color = new_color;
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+        }
+        glyphs.push((char, color));
+        rest = &rest[char.len_utf8()..];
+    }
+    glyphs
+}
+
+//Which font draws a character, None for spaces the font leaves blank
+//Anything the font lacks falls back to the default font, which has a little of everything
+fn resolve_glyph<'a>(
+    char: char,
+    font: &'a BitmapFont,
+    fallback: &'a BitmapFont,
+) -> Option<(&'a BitmapFont, GlyphInfo)> {
+    if let Some(glyph) = font.glyph(char) {
+        return Some((font, glyph));
+    }
+    if char == ' ' {
+        return None;
+    }
+    fallback
+        .glyph(substitute_char(char))
+        .or_else(|| fallback.glyph(MISSING_CHAR))
+        .map(|glyph| (fallback, glyph))
+}
+
+//Words longer than a whole line get cut wherever they hit the edge
+fn cut_word(
+    word: &[(char, Color)],
+    max_width: f32,
+    advance: &impl Fn(char) -> f32,
+) -> Vec<&[(char, Color)]> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut width = 0.0;
+    for (i, (char, _)) in word.iter().enumerate() {
+        //Always at least one character per piece so tiny widths still finish
+        if i > start && width + advance(*char) > max_width {
+            pieces.push(&word[start..i]);
+            start = i;
+            width = 0.0;
+        }
+        width += advance(*char);
+    }
+    pieces.push(&word[start..]);
+    pieces
+}
+
+//Breaks text into lines on newlines and wherever a word would go past max_width
+//Widths are in font pixels so they add up exactly
+fn layout_lines(
+    glyphs: Vec<(char, Color)>,
+    max_width: Option<f32>,
+    color: Color,
+    advance: impl Fn(char) -> f32,
+) -> Vec<Vec<(char, Color)>> {
+    let width = |line: &[(char, Color)]| line.iter().map(|(char, _)| advance(*char)).sum::<f32>();
+    let mut lines = Vec::new();
+    for paragraph in glyphs.split(|(char, _)| *char == '\n') {
+        let max_width = match max_width {
+            Some(max_width) => max_width,
+            None => {
+                lines.push(paragraph.to_vec());
+                continue;
+            }
+        };
+
+        let mut line = Vec::new();
+        for (i, word) in paragraph.split(|(char, _)| *char == ' ').enumerate() {
+            let pieces = if word.is_empty() {
+                //Keeps runs of spaces, they're usually on purpose
+                vec![word]
+            } else {
+                cut_word(word, max_width, &advance)
+            };
+            for (j, piece) in pieces.iter().enumerate() {
+                //Spaces only go back between words, not between pieces of a cut word
+                let spaced = i > 0 && j == 0;
+                let space = if spaced { advance(' ') } else { 0.0 };
+                if !line.is_empty() && width(&line) + space + width(piece) > max_width {
+                    lines.push(std::mem::take(&mut line));
+                } else if spaced {
+                    line.push((' ', color));
+                }
+                line.extend_from_slice(piece);
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+fn layout_glyphs(
     to_print: &str,
-) -> Entity {
-    //Clear existing text
-    let (entity, children) = to_update;
-    for child in children.iter() {
-        commands.entity(*child).despawn_recursive();
+    color: Color,
+    layout: TextLayout,
+    fonts: &Fonts,
+    font_assets: &Assets<BitmapFont>,
+) -> Vec<Glyph> {
+    let font = font_assets.get(fonts.get(layout.font)).unwrap();
+    let fallback = font_assets.get(fonts.get(FontId::Ascii)).unwrap();
+    let advance = |char| match resolve_glyph(char, font, fallback) {
+        Some((_, glyph)) => glyph.advance,
+        None => font.space(),
+    };
+
+    //Everything is worked out in the font's pixels then scaled, a line is always one tile tall
+    let tile = font.line_height;
+    let scale = font.pixel_size();
+    let glyphs = parse_markup(to_print, color);
+    let max_width = layout.max_width.map(|tiles| tiles as f32 * tile);
+
+    let mut positioned = Vec::new();
+    for (row, line) in layout_lines(glyphs, max_width, color, advance)
+        .iter()
+        .enumerate()
+    {
+        let width: f32 = line.iter().map(|(char, _)| advance(*char)).sum();
+        let mut x = match layout.align {
+            TextAlign::Left => -tile / 2.0,
+            TextAlign::Center => -width / 2.0,
+            TextAlign::Right => tile / 2.0 - width,
+        };
+        let baseline = tile / 2.0 - row as f32 * tile - font.baseline;
+        for (char, color) in line.iter() {
+            if let Some((glyph_font, glyph)) = resolve_glyph(*char, font, fallback) {
+                let center = Vec2::new(
+                    x + glyph.size.x / 2.0,
+                    baseline + glyph.bottom + glyph.size.y / 2.0,
+                );
+                positioned.push(Glyph {
+                    atlas: glyph_font.atlas.clone(),
+                    index: glyph.index,
+                    size: glyph.size * scale,
+                    color: *color,
+                    position: (center * scale).extend(0.0),
+                });
+            }
+            x += advance(*char);
+        }
     }
+    positioned
+}
 
-    //Update existing entity with new children
-    //XXX does this make sense, would it be clearer to just delete and create a new entire string?
-    //    probably not because of posistioning but maybe if wrapping is a problem... hmmmm...
-    let sprites = create_text(commands, ascii, Color::GREEN, to_print);
-    let name = format!("Text - {}", to_print);
+fn spawn_glyph(
+    commands: &mut Commands,
+    glyph: &Glyph,
+    parent_transform: &GlobalTransform,
+) -> Entity {
+    let mut sprite = TextureAtlasSprite::new(glyph.index);
+    sprite.custom_size = Some(glyph.size);
+    sprite.color = glyph.color;
+    let transform = Transform::from_translation(glyph.position);
 
     commands
-        .entity(entity)
-        .insert(Name::new(name))
-        .push_children(&sprites)
+        .spawn_bundle(SpriteSheetBundle {
+            sprite: sprite,
+            texture_atlas: glyph.atlas.clone(),
+            transform: transform,
+            global_transform: parent_transform.mul_transform(transform),
+            ..Default::default()
+        })
         .id()
 }
 
-fn create_text(
-    commands: &mut Commands,
-    ascii: AsciiSheet,
-    color: Color,
-    to_print: &str,
-) -> Vec<Entity> {
-    let mut sprites = Vec::new();
-    for (i, char) in to_print.chars().enumerate() {
-        //https://doc.rust-lang.org/std/primitive.char.html#representation
-        //"char is always 4 bytes", spritesheet only has 256 images
-        assert!(char as usize <= 255);
-        sprites.push(spawn_ascii_sprite(
-            commands,
-            &ascii,
-            char as usize,
-            color,
-            Vec3::new(i as f32 * TILE_SIZE, 0.0, 0.0),
-            Vec3::splat(1.0),
-        ));
-    }
-    sprites
-}
-
-pub fn spawn_ascii_text(
+pub fn spawn_ascii_text(commands: &mut Commands, to_print: &str, left_center: Vec3) -> Entity {
+    spawn_ascii_text_with_layout(commands, to_print, left_center, TextLayout::default())
+}
+
+//Position is where the first line goes, see TextAlign for which part of it
+//Glyphs show up once update_text_widgets runs at the end of the frame
+pub fn spawn_ascii_text_with_layout(
     commands: &mut Commands,
-    ascii: AsciiSheet,
     to_print: &str,
-    left_center: Vec3,
-    id: usize,
+    position: Vec3,
+    layout: TextLayout,
 ) -> Entity {
-    let color = Color::rgb(0.8, 0.8, 0.8);
-
-    let sprites = create_text(commands, ascii, color, to_print);
     let name = format!("Text - {}", to_print);
 
     commands
         .spawn()
         .insert(Name::new(name))
-        .insert(AsciiText { id })
+        .insert(TextWidget {
+            text: to_print.to_string(),
+            color: Color::rgb(0.8, 0.8, 0.8),
+            layout: layout,
+        })
+        .insert(TextGlyphs {
+            sprites: Vec::new(),
+            shown: Vec::new(),
+        })
         //Needs transforms for parent heirarchy system to work
         .insert(Transform {
-            translation: left_center,
+            translation: position,
             ..Default::default()
         })
         .insert(GlobalTransform::default())
-        .push_children(&sprites)
         .id()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(line: &[(char, Color)]) -> String {
+        line.iter().map(|(char, _)| *char).collect()
+    }
+
+    //Every character one pixel wide so widths are just lengths
+    fn layout(to_print: &str, max_width: Option<f32>) -> Vec<String> {
+        let glyphs = parse_markup(to_print, Color::WHITE);
+        layout_lines(glyphs, max_width, Color::WHITE, |_| 1.0)
+            .iter()
+            .map(|line| text(line))
+            .collect()
+    }
+
+    #[test]
+    fn markup_colors_until_closed() {
+        let glyphs = parse_markup("{red}hi{/}!", Color::WHITE);
+        assert_eq!(
+            glyphs,
+            vec![('h', Color::RED), ('i', Color::RED), ('!', Color::WHITE)]
+        );
+    }
+
+    #[test]
+    fn markup_takes_hex_colors() {
+        let glyphs = parse_markup("{#ff8800}a", Color::WHITE);
+        assert_eq!(glyphs, vec![('a', Color::hex("ff8800").unwrap())]);
+    }
+
+    #[test]
+    fn markup_escapes_and_bad_tags_are_printed() {
+        assert_eq!(text(&parse_markup("{{red}", Color::WHITE)), "{red}");
+        assert_eq!(text(&parse_markup("{nope}a", Color::WHITE)), "{nope}a");
+        assert_eq!(text(&parse_markup("{red", Color::WHITE)), "{red");
+        assert!(parse_markup("{nope}a", Color::WHITE)
+            .iter()
+            .all(|(_, color)| *color == Color::WHITE));
+    }
+
+    #[test]
+    fn lines_split_on_newlines() {
+        assert_eq!(layout("one\ntwo", None), vec!["one", "two"]);
+        assert_eq!(layout("one\n\ntwo", Some(10.0)), vec!["one", "", "two"]);
+    }
+
+    #[test]
+    fn words_wrap_at_max_width() {
+        assert_eq!(
+            layout("the quick brown fox", Some(10.0)),
+            vec!["the quick", "brown fox"]
+        );
+        //Exactly full lines still fit
+        assert_eq!(layout("abc def", Some(7.0)), vec!["abc def"]);
+    }
+
+    #[test]
+    fn long_words_get_cut() {
+        assert_eq!(
+            layout("abcdefghijkl", Some(5.0)),
+            vec!["abcde", "fghij", "kl"]
+        );
+        assert_eq!(layout("ab abcdefg", Some(5.0)), vec!["ab", "abcde", "fg"]);
+    }
+
+    #[test]
+    fn runs_of_spaces_are_kept() {
+        assert_eq!(layout("a  b", Some(10.0)), vec!["a  b"]);
+    }
+
+    #[test]
+    fn wrapping_keeps_markup_colors() {
+        let glyphs = parse_markup("aa {red}bb{/}", Color::WHITE);
+        let lines = layout_lines(glyphs, Some(3.0), Color::WHITE, |_| 1.0);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1], vec![('b', Color::RED), ('b', Color::RED)]);
+    }
+}
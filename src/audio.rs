@@ -1,5 +1,13 @@
+use crate::animation::AnimationEvent;
+use crate::input::GameAction;
+use crate::player::Player;
+use crate::settings::Settings;
 use bevy::{asset::LoadState, prelude::*, utils::HashMap};
-use bevy_kira_audio::{Audio, AudioPlugin, AudioSource, InstanceHandle, PlaybackState};
+use bevy_kira_audio::{
+    Audio, AudioChannel, AudioPlugin, AudioSource, InstanceHandle, PlaybackState,
+};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
 
 pub struct AudioManagerPlugin;
 
@@ -9,77 +17,171 @@ impl Plugin for AudioManagerPlugin {
             .add_startup_system_to_stage(StartupStage::PreStartup, load_audio)
             .add_system(temp_volume_control)
             .add_system(set_audio_volume)
-            .add_system(check_audio_loading);
+            .add_system(check_audio_loading)
+            .add_system(play_footsteps.after("animate_sprites"));
     }
 }
 
-#[derive(Hash, PartialEq, Eq)]
-pub enum Clips {
-    Hit,
+//Each has its own volume, scaled by the main volume
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundChannel {
+    Music,
+    Sfx,
+    Ui,
 }
 
-pub struct AudioClip {
-    pub loaded: bool,
-    pub handle: Handle<AudioSource>,
-    instance: Option<InstanceHandle>,
+pub struct Sound {
+    loaded: bool,
+    handle: Handle<AudioSource>,
+    //Every sound gets its own channel so it can have its own volume
+    channel: AudioChannel,
+    volume: f32,
+    kind: SoundChannel,
+    //Plays are skipped while this many are still going
+    max_instances: usize,
+    instances: Vec<InstanceHandle>,
 }
 
 pub struct AudioState {
-    pub clips: HashMap<Clips, AudioClip>,
+    //Keyed by the names in sounds.txt
+    pub sounds: HashMap<String, Sound>,
     pub main_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub ui_volume: f32,
 }
 
-fn temp_volume_control(mut audio_state: ResMut<AudioState>, keyboard: Res<Input<KeyCode>>) {
-    if keyboard.just_pressed(KeyCode::Up) {
-        audio_state.main_volume += 0.10;
+impl AudioState {
+    pub fn channel_volume(&self, channel: SoundChannel) -> f32 {
+        let volume = match channel {
+            SoundChannel::Music => self.music_volume,
+            SoundChannel::Sfx => self.sfx_volume,
+            SoundChannel::Ui => self.ui_volume,
+        };
+        volume * self.main_volume
     }
-    if keyboard.just_pressed(KeyCode::Down) {
-        audio_state.main_volume -= 0.10;
+
+    //Overlaps with any earlier plays of the same sound that are still going
+    pub fn play(&mut self, audio: &Audio, name: &str) {
+        let sound = match self.sounds.get_mut(name) {
+            Some(sound) => sound,
+            None => {
+                println!("No sound named {}", name);
+                return;
+            }
+        };
+        //Late sounds are worse than missing ones
+        if !sound.loaded {
+            return;
+        }
+        sound
+            .instances
+            .retain(|instance| audio.state(instance.clone()) != PlaybackState::Stopped);
+        if sound.instances.len() >= sound.max_instances {
+            return;
+        }
+        let instance = audio.play_in_channel(sound.handle.clone(), &sound.channel);
+        sound.instances.push(instance);
+    }
+}
+
+//Shortcut for the master volume in the options, not saved until the options are closed
+fn temp_volume_control(mut settings: ResMut<Settings>, actions: Res<Input<GameAction>>) {
+    let mut volume = settings.master_volume;
+    if actions.just_pressed(GameAction::VolumeUp) {
+        volume += 0.10;
+    }
+    if actions.just_pressed(GameAction::VolumeDown) {
+        volume -= 0.10;
     }
     //Behavior is weird outside of this range
-    audio_state.main_volume = audio_state.main_volume.clamp(0.0, 1.0);
+    let volume = volume.clamp(0.0, 1.0);
+    if volume != settings.master_volume {
+        settings.master_volume = volume;
+    }
 }
 
 fn check_audio_loading(mut audio_state: ResMut<AudioState>, asset_server: ResMut<AssetServer>) {
-    for mut clip in &mut audio_state.clips.values_mut() {
-        if !clip.loaded && asset_server.get_load_state(&clip.handle) == LoadState::Loaded {
-            clip.loaded = true;
+    for sound in audio_state.sounds.values_mut() {
+        if !sound.loaded && asset_server.get_load_state(&sound.handle) == LoadState::Loaded {
+            sound.loaded = true;
         }
     }
 }
 
 fn set_audio_volume(audio: Res<Audio>, audio_state: Res<AudioState>) {
     if audio_state.is_changed() {
-        audio.set_volume(audio_state.main_volume);
+        for sound in audio_state.sounds.values() {
+            let volume = sound.volume * audio_state.channel_volume(sound.kind);
+            audio.set_volume_in_channel(volume, &sound.channel);
+        }
     }
 }
 
 fn load_audio(mut commands: Commands, asset_server: ResMut<AssetServer>) {
-    let hit_handle = asset_server.load("hit.wav");
     let mut audio_state = AudioState {
-        clips: HashMap::default(),
+        sounds: HashMap::default(),
         main_volume: 0.5,
+        music_volume: 1.0,
+        sfx_volume: 1.0,
+        ui_volume: 1.0,
     };
-    audio_state.clips.insert(
-        Clips::Hit,
-        AudioClip {
-            loaded: false,
-            handle: hit_handle,
-            instance: None,
-        },
-    );
+
+    let input = File::open("assets/sounds.txt").expect("No sound list found");
+    for line in BufReader::new(input).lines().flatten() {
+        if line.starts_with('/') || line.trim().is_empty() {
+            continue;
+        }
+        match parse_sound(&line, &asset_server) {
+            Some((name, sound)) => {
+                audio_state.sounds.insert(name, sound);
+            }
+            None => println!("Bad sound formatting: {}", line),
+        }
+    }
 
     commands.insert_resource(audio_state);
 }
 
-#[allow(dead_code)]
-pub fn play_single_sound(audio: Res<Audio>, clip: &mut AudioClip) {
-    if !clip.loaded {
-        return;
+//name file volume channel max_instances
+fn parse_sound(line: &str, asset_server: &AssetServer) -> Option<(String, Sound)> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    if words.len() != 5 {
+        return None;
     }
-    if clip.instance == None
-        || audio.state(clip.instance.clone().unwrap()) == PlaybackState::Stopped
+    let kind = match words[3] {
+        "music" => SoundChannel::Music,
+        "sfx" => SoundChannel::Sfx,
+        "ui" => SoundChannel::Ui,
+        _ => return None,
+    };
+    let sound = Sound {
+        loaded: false,
+        handle: asset_server.load(words[1]),
+        channel: AudioChannel::new(format!("sound_{}", words[0])),
+        volume: words[2].parse().ok()?,
+        kind: kind,
+        max_instances: words[4].parse().ok()?,
+        instances: Vec::new(),
+    };
+    Some((words[0].to_string(), sound))
+}
+
+//Only the player's steps are audible, npcs would be too noisy
+fn play_footsteps(
+    mut events: EventReader<AnimationEvent>,
+    player_query: Query<Entity, With<Player>>,
+    audio: Res<Audio>,
+    mut audio_state: ResMut<AudioState>,
+) {
+    let player = match player_query.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    if events
+        .iter()
+        .any(|event| event.entity == player && event.name == "footstep")
     {
-        clip.instance = Some(audio.play(clip.handle.clone()));
+        audio_state.play(&audio, "footstep");
     }
 }
@@ -0,0 +1,296 @@
+use bevy::prelude::*;
+use bevy::render::camera::ScalingMode;
+use bevy::transform::TransformSystem;
+
+use crate::settings::BASE_HEIGHT;
+use crate::tilemap::MapBounds;
+use crate::{GameState, RESOLUTION, TILE_SIZE};
+
+//Tiles are 16 pixels in their textures
+pub const PIXELS_PER_UNIT: f32 = 16.0 / TILE_SIZE;
+//Big enough to cover any window from the edge of the view outwards
+const BAR_SIZE: f32 = 100.0;
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ViewSize>()
+            .init_resource::<CameraFollow>()
+            .add_startup_system(spawn_camera)
+            //Combat moves the camera so coming back shouldn't drift across the map
+            .add_system_set(SystemSet::on_enter(GameState::Overworld).with_system(snap_camera))
+            //Before Update so everything sees this frame's size
+            .add_system_to_stage(CoreStage::PreUpdate, fit_view_to_window)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                anchor_to_screen.before(TransformSystem::TransformPropagate),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                snap_camera_to_pixels.after(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+//The part of the world on screen, always 16:9 with black bars over the rest of the window
+//Grows in whole pixel steps instead of stretching so every texture pixel stays the same size
+pub struct ViewSize {
+    pub half_width: f32,
+    pub half_height: f32,
+    //One screen pixel in world units
+    pub pixel: f32,
+}
+
+impl Default for ViewSize {
+    fn default() -> Self {
+        ViewSize {
+            half_width: RESOLUTION,
+            half_height: 1.0,
+            pixel: 1.0 / PIXELS_PER_UNIT,
+        }
+    }
+}
+
+//How the overworld camera chases the player
+pub struct CameraFollow {
+    //How quickly the camera catches up, higher is snappier and 0 keeps it locked on
+    pub smoothing: f32,
+    //How far the player can get from the center before the camera moves
+    pub dead_zone: Vec2,
+    //Jumps straight to the player next frame instead of easing over
+    pub snap: bool,
+}
+
+impl Default for CameraFollow {
+    fn default() -> Self {
+        CameraFollow {
+            smoothing: 8.0,
+            dead_zone: Vec2::new(1.5 * TILE_SIZE, TILE_SIZE),
+            snap: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Center,
+    Right,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Anchor {
+    //Which way from the center of the screen, -1 to 1 on each axis
+    fn direction(&self) -> Vec2 {
+        match self {
+            Anchor::TopLeft => Vec2::new(-1.0, 1.0),
+            Anchor::Center => Vec2::ZERO,
+            Anchor::Right => Vec2::new(1.0, 0.0),
+            Anchor::BottomLeft => Vec2::new(-1.0, -1.0),
+            Anchor::BottomRight => Vec2::new(1.0, -1.0),
+        }
+    }
+}
+
+//Keeps a root entity's center offset from a point on the edge of the screen
+//Only x and y are moved, z is left for layering
+#[derive(Component, Clone, Copy)]
+pub struct ScreenAnchor {
+    pub anchor: Anchor,
+    pub offset: Vec2,
+}
+
+#[derive(Component)]
+struct LetterboxBar {
+    direction: Vec2,
+}
+
+fn spawn_camera(mut commands: Commands) {
+    let mut camera = OrthographicCameraBundle::new_2d();
+    //Scale is set to a whole number of pixels per texture pixel once the window size is known
+    camera.orthographic_projection.scaling_mode = ScalingMode::WindowSize;
+    camera.orthographic_projection.scale = 1.0 / (PIXELS_PER_UNIT * 3.0);
+    commands.spawn_bundle(camera).with_children(|parent| {
+        for direction in [
+            Vec2::new(-1.0, 0.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(0.0, 1.0),
+            Vec2::new(0.0, -1.0),
+        ] {
+            parent
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::BLACK,
+                        custom_size: Some(Vec2::splat(BAR_SIZE)),
+                        ..Default::default()
+                    },
+                    //Just under the camera so it's over everything else
+                    transform: Transform::from_xyz(0.0, 0.0, -0.01),
+                    ..Default::default()
+                })
+                .insert(Name::new("LetterboxBar"))
+                .insert(LetterboxBar {
+                    direction: direction,
+                });
+        }
+    });
+}
+
+fn fit_view_to_window(
+    windows: Res<Windows>,
+    mut view: ResMut<ViewSize>,
+    mut camera_query: Query<&mut OrthographicProjection, With<Camera>>,
+    mut bar_query: Query<(&LetterboxBar, &mut Transform)>,
+    mut last_size: Local<Vec2>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    //Physical pixels so high dpi screens still line up with texture pixels
+    let size = Vec2::new(
+        window.physical_width() as f32,
+        window.physical_height() as f32,
+    );
+    if size == *last_size || size.y <= 0.0 {
+        return;
+    }
+    *last_size = size;
+
+    let view_height = size.y.min(size.x / RESOLUTION);
+    //Biggest whole number scale that still shows at least the normal 20 tiles
+    let scale = (view_height / BASE_HEIGHT).floor().max(1.0);
+    let pixel = 1.0 / (PIXELS_PER_UNIT * scale);
+    view.pixel = pixel;
+    view.half_height = view_height / 2.0 * pixel;
+    view.half_width = view.half_height * RESOLUTION;
+
+    for mut projection in camera_query.iter_mut() {
+        //The projection works in logical pixels
+        projection.scale = pixel * window.scale_factor() as f32;
+    }
+    let half_size = Vec2::new(view.half_width, view.half_height);
+    for (bar, mut transform) in bar_query.iter_mut() {
+        let position = bar.direction * (half_size + BAR_SIZE / 2.0);
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
+
+//Keeps the view inside the map, maps smaller than the view are centered instead
+pub fn clamp_to_bounds(center: Vec2, bounds: &MapBounds, view: &ViewSize) -> Vec2 {
+    let half_size = Vec2::new(view.half_width, view.half_height);
+    let clamp_axis = |center: f32, min: f32, max: f32, half: f32| {
+        if max - min <= 2.0 * half {
+            (min + max) / 2.0
+        } else {
+            center.clamp(min + half, max - half)
+        }
+    };
+    Vec2::new(
+        clamp_axis(center.x, bounds.min.x, bounds.max.x, half_size.x),
+        clamp_axis(center.y, bounds.min.y, bounds.max.y, half_size.y),
+    )
+}
+
+fn snap_camera(mut follow: ResMut<CameraFollow>) {
+    follow.snap = true;
+}
+
+fn snap(position: Vec2, pixel: f32) -> Vec2 {
+    (position / pixel).round() * pixel
+}
+
+fn anchor_to_screen(
+    view: Res<ViewSize>,
+    camera_query: Query<&Transform, With<Camera>>,
+    mut anchor_query: Query<(&ScreenAnchor, &mut Transform), Without<Camera>>,
+) {
+    let camera = match camera_query.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    //Matches where the camera is drawn from so anchored things don't shimmer
+    let center = snap(camera.translation.truncate(), view.pixel);
+    let half_size = Vec2::new(view.half_width, view.half_height);
+    for (anchor, mut transform) in anchor_query.iter_mut() {
+        let position = center + anchor.anchor.direction() * half_size + anchor.offset;
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
+
+//The camera's transform is left alone so smooth movement isn't lost, it's only drawn snapped
+fn snap_camera_to_pixels(
+    view: Res<ViewSize>,
+    mut camera_query: Query<(&Transform, &mut GlobalTransform), With<Camera>>,
+) {
+    for (transform, mut global) in camera_query.iter_mut() {
+        let snapped = snap(transform.translation.truncate(), view.pixel);
+        global.translation.x = snapped.x;
+        global.translation.y = snapped.y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(half_width: f32, half_height: f32) -> ViewSize {
+        ViewSize {
+            half_width: half_width,
+            half_height: half_height,
+            pixel: 1.0 / PIXELS_PER_UNIT,
+        }
+    }
+
+    fn bounds(min: Vec2, max: Vec2) -> MapBounds {
+        MapBounds { min: min, max: max }
+    }
+
+    #[test]
+    fn centers_inside_the_map_are_kept() {
+        let bounds = bounds(Vec2::ZERO, Vec2::new(10.0, 10.0));
+        let center = Vec2::new(5.0, 4.0);
+        assert_eq!(clamp_to_bounds(center, &bounds, &view(2.0, 1.0)), center);
+    }
+
+    #[test]
+    fn edges_of_the_view_stop_at_the_map_edges() {
+        let bounds = bounds(Vec2::ZERO, Vec2::new(10.0, 10.0));
+        let view = view(2.0, 1.0);
+        assert_eq!(
+            clamp_to_bounds(Vec2::new(-5.0, 20.0), &bounds, &view),
+            Vec2::new(2.0, 9.0)
+        );
+        assert_eq!(
+            clamp_to_bounds(Vec2::new(9.5, 0.5), &bounds, &view),
+            Vec2::new(8.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn maps_smaller_than_the_view_are_centered() {
+        let bounds = bounds(Vec2::new(-1.0, -3.0), Vec2::new(2.0, 1.0));
+        let view = view(4.0, 4.0);
+        for center in [Vec2::ZERO, Vec2::new(100.0, -100.0), Vec2::new(-3.0, 2.0)] {
+            assert_eq!(
+                clamp_to_bounds(center, &bounds, &view),
+                Vec2::new(0.5, -1.0)
+            );
+        }
+    }
+
+    #[test]
+    fn only_the_small_axis_is_centered() {
+        //Wide enough to scroll sideways but shorter than the view
+        let bounds = bounds(Vec2::ZERO, Vec2::new(20.0, 1.0));
+        let view = view(2.0, 1.5);
+        assert_eq!(
+            clamp_to_bounds(Vec2::new(30.0, 5.0), &bounds, &view),
+            Vec2::new(18.0, 0.5)
+        );
+    }
+}
@@ -1,9 +1,19 @@
-use crate::ascii::{spawn_ascii_text, update_ascii_text, AsciiText};
-use crate::audio::{play_single_sound, AudioState, Clips};
+use crate::ascii::spawn_ascii_sprite;
+use crate::audio::AudioState;
+use crate::camera::{Anchor, ScreenAnchor, ViewSize};
+use crate::combat_animation::{spawn_attack_animation, CombatAnimation};
+use crate::combat_hud::spawn_combat_hud;
+use crate::combat_log::CombatLog;
 use crate::debug::ENABLE_INSPECTOR;
-use crate::enemy::{create_enemy, destroy_enemy, Enemy, HEALTH_UI_ID};
-use crate::nine_sprite::{spawn_nine_sprite, NineSprite, NineSpriteIndices};
-use crate::{AsciiSheet, GameState, RESOLUTION, TILE_SIZE};
+use crate::enemy::{create_enemy, destroy_enemy, Encounter, Enemy, EnemySprite};
+use crate::input::{GameAction, InputLock};
+use crate::music::{Music, VICTORY_JINGLE};
+use crate::nine_sprite::NineSpriteTheme;
+use crate::screen_fadeout::{create_fadeout, fadeout, TransitionStyles};
+use crate::tween::{Ease, Tween, TweenTarget, Tweener};
+use crate::ui::{spawn_button, spawn_list, Focused, ListConfirmed};
+use crate::wild::Engaged;
+use crate::{AsciiSheet, GameState, TILE_SIZE};
 use bevy::prelude::*;
 use bevy_inspector_egui::{Inspectable, RegisterInspectable};
 use bevy_kira_audio::Audio;
@@ -16,45 +26,118 @@ enum CombatMenuType {
     Run,
 }
 
+//Same order as the buttons in the menu's list
+const COMBAT_MENU_ORDER: [CombatMenuType; 4] = [
+    CombatMenuType::Fight,
+    CombatMenuType::Swap,
+    CombatMenuType::Item,
+    CombatMenuType::Run,
+];
+
 #[derive(Component, Inspectable)]
 struct CombatMenu {
     active: bool,
-    selected: CombatMenuType,
+    //The list of buttons, the menu itself only handles sliding in and out
+    #[inspectable(ignore)]
+    list: Entity,
+    //Moves the list in and out, the menu itself stays anchored to the corner
+    #[inspectable(ignore)]
+    slider: Entity,
 }
 
 struct FightEvent;
 
-#[derive(Component, Inspectable)]
-struct CombatMenuButton {
-    id: CombatMenuType,
+//Sent once the screen is covered on the way back to the overworld
+#[derive(Clone)]
+struct LeaveCombat;
+
+//Where the fight is at, the battle only ends once the log has been read
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CombatPhase {
+    Choosing,
+    Won,
+    Fled,
+    //Waiting on the transition back to the overworld
+    Leaving,
+}
+
+impl Default for CombatPhase {
+    fn default() -> Self {
+        CombatPhase::Choosing
+    }
+}
+
+//Anything that takes part in a fight, the HUD shows these
+#[derive(Component, Inspectable, Clone)]
+pub struct Combatant {
+    pub name: String,
+    pub level: i64,
 }
 
+//Kept separate so the HUD can watch for changes to just this
+#[derive(Component, Inspectable, Clone, Copy)]
+pub struct Health {
+    pub current: i64,
+    pub max: i64,
+}
+
+impl Health {
+    pub fn new(max: i64) -> Health {
+        Health {
+            current: max,
+            max: max,
+        }
+    }
+}
+
+//The player's side of the fight
+//TODO there's no party yet so it's always the same monster
+#[derive(Component)]
+struct PlayerMonster;
+
 pub struct CombatPlugin;
 
 impl Plugin for CombatPlugin {
     fn build(&self, app: &mut App) {
         app.add_system_set(SystemSet::on_update(GameState::Combat))
             .add_event::<FightEvent>()
+            .add_event::<LeaveCombat>()
+            .add_system(fadeout::<LeaveCombat>)
+            .init_resource::<Encounter>()
+            .init_resource::<CombatPhase>()
             .add_system_set(
                 SystemSet::on_enter(GameState::Combat)
+                    .with_system(reset_combat_phase)
                     .with_system(center_camera)
                     .with_system(create_combat_menu)
-                    .with_system(create_enemy),
+                    .with_system(create_enemy)
+                    .with_system(create_player_monster),
             )
             .add_system_set(
                 SystemSet::on_update(GameState::Combat)
-                    .with_system(highlight_selected_menu)
-                    .with_system(combat_menu_input)
-                    .with_system(fight),
+                    //Before the log so the press that closes a message doesn't also pick a button
+                    .with_system(
+                        combat_menu_input
+                            .after("ui_navigation")
+                            .before("combat_log"),
+                    )
+                    .with_system(fight.label("fight"))
+                    //fight lowers health right away but its animation only exists once its
+                    //commands are applied at the end of the stage
+                    //Checking first means the lowered health is never seen without the animation
+                    .with_system(check_victory.before("fight"))
+                    .with_system(leave_combat),
             )
             .add_system_set(
                 SystemSet::on_exit(GameState::Combat)
                     .with_system(delete_combat_menu)
-                    .with_system(destroy_enemy),
+                    .with_system(destroy_enemy)
+                    .with_system(destroy_player_monster),
             );
         if ENABLE_INSPECTOR {
-            app.register_inspectable::<CombatMenuButton>()
-                .register_inspectable::<Enemy>()
+            app.register_inspectable::<Enemy>()
+                .register_inspectable::<Combatant>()
+                .register_inspectable::<Health>()
                 .register_inspectable::<CombatMenu>();
         }
     }
@@ -65,228 +148,247 @@ impl Plugin for CombatPlugin {
 fn fight(
     mut commands: Commands,
     mut event: EventReader<FightEvent>,
-    mut enemy_query: Query<&mut Enemy>,
-    text_query: Query<(Entity, &Children, &AsciiText)>,
+    mut enemy_query: Query<(&Enemy, &Combatant, &mut Health)>,
+    player_query: Query<(Entity, &Combatant, &Transform), With<PlayerMonster>>,
+    sprite_query: Query<(Entity, &Transform), With<EnemySprite>>,
+    mut log: ResMut<CombatLog>,
     mut audio_state: ResMut<AudioState>,
     audio: Res<Audio>,
-    ascii: Res<AsciiSheet>,
-    mut state: ResMut<State<GameState>>,
 ) {
     if event.iter().next().is_none() {
         return;
     }
     //TODO support multiple enemies
-    let mut enemy = enemy_query.single_mut();
-    enemy.health -= 1;
+    let (enemy, target, mut health) = enemy_query.single_mut();
+    let damage = 1;
+    health.current = (health.current - damage).max(0);
 
-    for (text, text_children, id) in text_query.iter() {
-        if id.id == HEALTH_UI_ID {
-            update_ascii_text(
-                &mut commands,
-                (text, text_children),
-                ascii.clone(),
-                &format!("Health: {}", enemy.health),
-            );
+    let (attacker, attacker_combatant, attacker_transform) = player_query.single();
+    log.push(&format!("{} attacks!", attacker_combatant.name));
+    log.push(&format!("{} took {} damage!", target.name, damage));
+
+    let (sprite, sprite_transform) = sprite_query.single();
+    spawn_attack_animation(
+        &mut commands,
+        attacker,
+        attacker_transform,
+        sprite,
+        sprite_transform,
+        enemy.color,
+        damage,
+    );
+
+    audio_state.play(&audio, "hit");
+}
+
+fn reset_combat_phase(mut phase: ResMut<CombatPhase>) {
+    *phase = CombatPhase::Choosing;
+}
+
+//Waits for the hit animations to finish so the killing blow is visible
+//Then waits for the log so the player sees how the fight ended before leaving
+#[allow(clippy::too_many_arguments)]
+fn check_victory(
+    mut commands: Commands,
+    enemy_query: Query<(&Combatant, &Health), With<Enemy>>,
+    animation_query: Query<(), With<CombatAnimation>>,
+    engaged_query: Query<Entity, With<Engaged>>,
+    mut phase: ResMut<CombatPhase>,
+    mut log: ResMut<CombatLog>,
+    mut music: ResMut<Music>,
+    audio: Res<Audio>,
+    assets: Res<AssetServer>,
+    ascii: Res<AsciiSheet>,
+    styles: Res<TransitionStyles>,
+) {
+    if animation_query.iter().next().is_some() {
+        return;
+    }
+    match *phase {
+        CombatPhase::Choosing => {
+            //Enemy isn't spawned until commands from entering combat are applied
+            if let Ok((combatant, health)) = enemy_query.get_single() {
+                if health.current <= 0 {
+                    //TODO exp
+                    log.push(&format!("{} fainted!", combatant.name));
+                    log.push("You won!");
+                    music.play(&audio, &assets, VICTORY_JINGLE, false);
+                    *phase = CombatPhase::Won;
+                }
+            }
         }
+        CombatPhase::Won | CombatPhase::Fled => {
+            if !log.is_empty() {
+                return;
+            }
+            //Defeated overworld monsters don't come back
+            let style = if *phase == CombatPhase::Won {
+                for monster in engaged_query.iter() {
+                    commands.entity(monster).despawn_recursive();
+                }
+                styles.victory
+            } else {
+                styles.flee
+            };
+            create_fadeout(&mut commands, ascii.clone(), LeaveCombat, 0.8, style);
+            *phase = CombatPhase::Leaving;
+        }
+        CombatPhase::Leaving => {}
     }
+}
 
-    play_single_sound(audio, audio_state.clips.get_mut(&Clips::Hit).unwrap());
-
-    if enemy.health <= 0 {
-        //TODO exp
-        println!("Win!");
+fn leave_combat(mut events: EventReader<LeaveCombat>, mut state: ResMut<State<GameState>>) {
+    if events.iter().next().is_some() {
         state
             .set(GameState::Overworld)
             .expect("Failed to change state");
     }
 }
 
+fn slide_menu(commands: &mut Commands, menu: Entity, from: Vec3, to: Vec3, ease: Ease) {
+    let slide = Tween::new(
+        TweenTarget::Translation {
+            start: from,
+            end: to,
+        },
+        0.3,
+        ease,
+    );
+    commands.entity(menu).insert(Tweener::new(slide));
+}
+
+#[allow(clippy::too_many_arguments)]
 fn combat_menu_input(
-    mut menu_query: Query<(&mut CombatMenu, &mut Transform)>,
+    mut commands: Commands,
+    mut menu_query: Query<&mut CombatMenu>,
+    slider_query: Query<&Transform>,
+    animation_query: Query<(), With<CombatAnimation>>,
+    mut confirmed: EventReader<ListConfirmed>,
     mut fight_event: EventWriter<FightEvent>,
-    keyboard: Res<Input<KeyCode>>,
-    mut state: ResMut<State<GameState>>,
+    actions: Res<Input<GameAction>>,
+    mut log: ResMut<CombatLog>,
+    mut phase: ResMut<CombatPhase>,
+    view: Res<ViewSize>,
+    input_lock: Res<InputLock>,
 ) {
-    let (mut menu, mut transform) = menu_query.single_mut();
+    let mut menu = menu_query.single_mut();
+    let slider = menu.slider;
+    let transform = slider_query.get(slider).expect("Combat menu has no slider");
+    //Read even while waiting so old presses don't fire once the animation ends
+    let confirmed = confirmed
+        .iter()
+        .filter(|event| event.list == menu.list)
+        .map(|event| COMBAT_MENU_ORDER[event.index].clone())
+        .last();
+
+    //Nothing happens until the screen has faded in, the last attack finishes playing out
+    //and every message is read
+    if input_lock.is_locked()
+        || animation_query.iter().next().is_some()
+        || !log.is_empty()
+        || *phase != CombatPhase::Choosing
+    {
+        return;
+    }
     if !menu.active {
-        if keyboard.just_pressed(KeyCode::Escape) {
+        if actions.just_pressed(GameAction::Cancel) {
             //TODO handle swap and item menus
-            transform.translation.x = 0.0;
+            slide_menu(
+                &mut commands,
+                slider,
+                transform.translation,
+                Vec3::ZERO,
+                Ease::BackOut,
+            );
+            commands.entity(menu.list).insert(Focused);
             menu.active = true;
         }
         return;
     }
 
-    let mut to_select = menu.selected.clone() as isize;
-
-    if keyboard.just_pressed(KeyCode::Return) {
-        match menu.selected {
-            CombatMenuType::Fight => {
-                fight_event.send(FightEvent);
-            }
-            CombatMenuType::Item => {
-                //Move menu ofTypeyyppeef screen when not in use
-                //No point to destroy and recreate
-                transform.translation.x = 1000.0;
-                menu.active = false;
-            }
-            CombatMenuType::Swap => {
-                transform.translation.x = 1000.0;
-                menu.active = false;
-            }
-            CombatMenuType::Run => {
-                println!("Battle End !");
-                state
-                    .set(GameState::Overworld)
-                    .expect("Failed to change state");
-            }
-        };
-    }
-
-    if keyboard.just_pressed(KeyCode::D) {
-        to_select += 1;
-    }
-    if keyboard.just_pressed(KeyCode::A) {
-        to_select -= 1;
-    }
-    if keyboard.just_pressed(KeyCode::W) {
-        to_select += 2;
-    }
-    if keyboard.just_pressed(KeyCode::S) {
-        to_select -= 2;
-    }
-
-    // add 4 to force positive outcome
-    to_select = (to_select + 4) % 4;
-
-    menu.selected = match to_select {
-        0 => CombatMenuType::Fight,
-        1 => CombatMenuType::Swap,
-        2 => CombatMenuType::Item,
-        3 => CombatMenuType::Run,
-        _ => unreachable!("Bad menu selection!"),
-    };
-}
-
-fn highlight_selected_menu(
-    menu_query: Query<&CombatMenu>,
-    button_query: Query<(&CombatMenuButton, &Children)>,
-    nine_sprite_query: Query<(&NineSprite, &Children)>,
-    mut child_query: Query<&mut TextureAtlasSprite>,
-) {
-    //TODO this weird mess needs a lot of work...
-    //How to climb and bevy hierarchy more effectively and genericly
-    let menu = menu_query.single();
-
-    for (element, children) in button_query.iter() {
-        //Highlight this buttons nine sprite
-        for &button_child in children.iter() {
-            if let Ok((_, nine_sprite_children)) = nine_sprite_query.get(button_child) {
-                //Highlight all children of the nine sprite red
-                for &child in nine_sprite_children.iter() {
-                    if let Ok(mut child_sprite) = child_query.get_mut(child) {
-                        //Only highlight if id is the selected option
-                        if element.id == menu.selected {
-                            (*child_sprite).color = Color::RED;
-                        } else {
-                            (*child_sprite).color = Color::WHITE;
-                        }
-                    }
-                }
-            }
+    match confirmed {
+        Some(CombatMenuType::Fight) => {
+            fight_event.send(FightEvent);
+        }
+        //Move menu off screen when not in use
+        //No point to destroy and recreate
+        Some(CombatMenuType::Item) | Some(CombatMenuType::Swap) => {
+            //The menu is anchored to the bottom right corner so a view's width past it
+            //is off screen however big the window is
+            slide_menu(
+                &mut commands,
+                slider,
+                transform.translation,
+                Vec3::new(2.0 * view.half_width, 0.0, 0.0),
+                Ease::QuadIn,
+            );
+            commands.entity(menu.list).remove::<Focused>();
+            menu.active = false;
+        }
+        Some(CombatMenuType::Run) => {
+            log.push("Got away safely!");
+            *phase = CombatPhase::Fled;
         }
+        None => {}
     }
 }
-fn create_combat_button(
-    commands: &mut Commands,
-    ascii: AsciiSheet,
-    indices: NineSpriteIndices,
-    translation: Vec3,
-    text: &str,
-    id: CombatMenuType,
-    size: Vec2,
-) -> Entity {
-    //XXX why -3 tiles
-    let text_offset = Vec3::new(-(size.x - 3.0 * TILE_SIZE) / 2.0, 0.0, 0.0);
-
-    let button = commands
-        .spawn()
-        .insert(Name::new(text.to_owned() + "Button"))
-        .insert(CombatMenuButton { id: id })
-        .insert(Transform {
-            translation: translation,
-            ..Default::default()
-        })
-        .insert(GlobalTransform::default())
-        .id();
-    let sprite = spawn_nine_sprite(commands, ascii.clone(), indices, size.x, size.y);
-    let text = spawn_ascii_text(commands, ascii, text, text_offset, 0);
-    commands.entity(button).push_children(&[sprite, text]);
-    button
-}
-
-fn create_combat_menu(
-    mut commands: Commands,
-    ascii: Res<AsciiSheet>,
-    indices: Res<NineSpriteIndices>,
-) {
-    let box_width = 7.0 * TILE_SIZE;
-    let box_height = 3.0 * TILE_SIZE;
-    let bottom_offset = -1.0 + box_height / 2.0;
-    let right_offset = 1.0 * RESOLUTION - box_width / 2.0;
-
-    let run = create_combat_button(
-        &mut commands,
-        ascii.clone(),
-        *indices,
-        Vec3::new(right_offset, bottom_offset, 0.0),
-        "Run",
-        CombatMenuType::Run,
-        Vec2::new(box_width, box_height),
-    );
-
-    let item = create_combat_button(
-        &mut commands,
-        ascii.clone(),
-        *indices,
-        Vec3::new(right_offset - box_width, bottom_offset, 0.0),
-        "Item",
-        CombatMenuType::Item,
-        Vec2::new(box_width, box_height),
-    );
 
-    let swap = create_combat_button(
+fn create_combat_menu(mut commands: Commands, ascii: Res<AsciiSheet>) {
+    let box_size = Vec2::new(7.0, 3.0) * TILE_SIZE;
+    let theme = NineSpriteTheme {
+        border_color: Color::WHITE,
+        shadow_color: Some(Color::rgba(0.0, 0.0, 0.0, 0.5)),
+        ..Default::default()
+    };
+    let buttons: Vec<Entity> = COMBAT_MENU_ORDER
+        .iter()
+        .map(|id| {
+            spawn_button(
+                &mut commands,
+                ascii.clone(),
+                theme,
+                &format!("{:?}", id),
+                box_size,
+            )
+        })
+        .collect();
+    //2x2 grid tucked into the bottom right corner
+    let list = spawn_list(
         &mut commands,
-        ascii.clone(),
-        *indices,
-        Vec3::new(right_offset, bottom_offset + box_height, 0.0),
-        "Swap",
-        CombatMenuType::Swap,
-        Vec2::new(box_width, box_height),
+        &buttons,
+        2,
+        box_size,
+        Vec3::new(-box_size.x, box_size.y, 0.0),
     );
+    commands.entity(list).insert(Focused);
 
-    let fight = create_combat_button(
-        &mut commands,
-        ascii.clone(),
-        *indices,
-        Vec3::new(right_offset - box_width, bottom_offset + box_height, 0.0),
-        "Fight",
-        CombatMenuType::Fight,
-        Vec2::new(box_width, box_height),
-    );
+    //Slides up from below the screen
+    let start = Vec3::new(0.0, -2.0 * box_size.y, 0.0);
+    let slider = commands
+        .spawn()
+        .insert(Name::new("CombatMenuSlider"))
+        //Needs transforms for parent heirarchy system to work
+        .insert(Transform::from_translation(start))
+        .insert(GlobalTransform::default())
+        .push_children(&[list])
+        .id();
+    slide_menu(&mut commands, slider, start, Vec3::ZERO, Ease::BackOut);
 
     commands
         .spawn()
         .insert(Name::new("CombatMenu"))
         .insert(CombatMenu {
             active: true,
-            selected: CombatMenuType::Fight,
+            list: list,
+            slider: slider,
+        })
+        .insert(ScreenAnchor {
+            anchor: Anchor::BottomRight,
+            offset: Vec2::ZERO,
         })
-        //Needs transforms for parent heirarchy system to work
         .insert(Transform::default())
         .insert(GlobalTransform::default())
-        .push_children(&[fight, run, item, swap])
-        .id();
+        .push_children(&[slider]);
 }
 
 fn delete_combat_menu(mut commands: Commands, mut menu_query: Query<Entity, With<CombatMenu>>) {
@@ -294,6 +396,50 @@ fn delete_combat_menu(mut commands: Commands, mut menu_query: Query<Entity, With
     commands.entity(menu).despawn_recursive();
 }
 
+fn create_player_monster(mut commands: Commands, ascii: Res<AsciiSheet>, view: Res<ViewSize>) {
+    let combatant = Combatant {
+        name: "Slime".to_string(),
+        level: 5,
+    };
+    let health = Health::new(12);
+
+    //Bottom left, across from the enemy and halfway to the edge of the view
+    //Not screen anchored since attacks move it around
+    let sprite = spawn_ascii_sprite(
+        &mut commands,
+        &ascii,
+        's' as usize,
+        Color::rgb(0.4, 0.8, 1.0),
+        Vec3::new(-0.5 * view.half_width, -0.1, 1.0),
+        Vec3::splat(3.0),
+    );
+    let entity = commands
+        .entity(sprite)
+        .insert(Name::new("PlayerMonster"))
+        .insert(PlayerMonster)
+        .insert(health)
+        .id();
+    spawn_combat_hud(
+        &mut commands,
+        &ascii,
+        entity,
+        &combatant,
+        &health,
+        Anchor::BottomLeft,
+        Vec3::new(TILE_SIZE, 6.0 * TILE_SIZE, 5.0),
+    );
+    commands.entity(entity).insert(combatant);
+}
+
+fn destroy_player_monster(
+    mut commands: Commands,
+    monster_query: Query<Entity, With<PlayerMonster>>,
+) {
+    for entity in monster_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
 fn center_camera(mut camera_query: Query<&mut Transform, With<Camera>>) {
     let mut cam_transform = camera_query.single_mut();
     cam_transform.translation.x = 0.0;
@@ -0,0 +1,220 @@
+use bevy::prelude::*;
+use rand::{thread_rng, Rng};
+
+use crate::ascii::{spawn_ascii_text_with_layout, TextAlign, TextLayout, TextWidget};
+use crate::tween::{Ease, Tween, TweenCompleted, TweenTarget, Tweener};
+use crate::{GameState, TILE_SIZE};
+
+//The attacker steps forward, the hit lands as it arrives, then it steps back
+const LUNGE_TIME: f32 = 0.15;
+const LUNGE_RETURN_TIME: f32 = 0.2;
+const LUNGE_DISTANCE: f32 = TILE_SIZE;
+const HIT_TIME: f32 = 0.4;
+//Each step of the shake flips sides, tilts and flashes
+const HIT_STEPS: usize = 8;
+//Radians, leans away from the side it's pushed to
+const HIT_TILT: f32 = 0.2;
+const DAMAGE_NUMBER_TIME: f32 = 0.7;
+//How far the damage number floats up before disappearing
+const DAMAGE_NUMBER_RISE: f32 = 0.25;
+//Starts big and shrinks to normal size as it floats
+const DAMAGE_NUMBER_POP: f32 = 1.5;
+const DAMAGE_NUMBER_COLOR: Color = Color::rgb(1.0, 0.3, 0.3);
+//Sent by the last tween of every combat animation
+const ANIMATION_DONE: &str = "combat_animation_done";
+
+pub struct CombatAnimationPlugin;
+
+impl Plugin for CombatAnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::Combat)
+                .with_system(finish_combat_animations.after("tweens")),
+        )
+        .add_system_set(SystemSet::on_exit(GameState::Combat).with_system(despawn_damage_numbers));
+    }
+}
+
+//Marks anything still animating, combat input waits until none are left
+#[derive(Component)]
+pub struct CombatAnimation;
+
+#[derive(Component)]
+struct DamageNumber;
+
+//Lunges the attacker at the target, then shakes and flashes the target and floats the damage above it
+pub fn spawn_attack_animation(
+    commands: &mut Commands,
+    attacker: Entity,
+    attacker_transform: &Transform,
+    target: Entity,
+    target_transform: &Transform,
+    target_color: Color,
+    damage: i64,
+) {
+    let origin = attacker_transform.translation;
+    let toward = (target_transform.translation - origin)
+        .truncate()
+        .normalize_or_zero();
+    let lunge = origin + (toward * LUNGE_DISTANCE).extend(0.0);
+    commands
+        .entity(attacker)
+        .insert(
+            Tweener::new(Tween::new(
+                TweenTarget::Translation {
+                    start: origin,
+                    end: lunge,
+                },
+                LUNGE_TIME,
+                Ease::QuadIn,
+            ))
+            .then(
+                Tween::new(
+                    TweenTarget::Translation {
+                        start: lunge,
+                        end: origin,
+                    },
+                    LUNGE_RETURN_TIME,
+                    Ease::QuadOut,
+                )
+                .with_event(ANIMATION_DONE),
+            ),
+        )
+        .insert(CombatAnimation);
+
+    commands
+        .entity(target)
+        .insert(hit_tweener(target_transform.translation, target_color))
+        .insert(CombatAnimation);
+
+    let text = damage.to_string();
+    //Drift sideways a bit so numbers from quick hits don't stack exactly
+    let x_jitter = thread_rng().gen_range(-1.0..1.0) * TILE_SIZE;
+    let start = Vec3::new(
+        x_jitter,
+        target_transform.translation.y + 2.0 * TILE_SIZE,
+        //Above the enemy
+        5.0,
+    );
+    let layout = TextLayout {
+        align: TextAlign::Center,
+        ..Default::default()
+    };
+    let clear = Color::rgba(1.0, 0.3, 0.3, 0.0);
+    let number = spawn_ascii_text_with_layout(commands, &text, start, layout);
+    commands
+        .entity(number)
+        //Hidden until the hit lands
+        .insert(TextWidget {
+            text: text,
+            color: clear,
+            layout: layout,
+        })
+        .insert(DamageNumber)
+        .insert(CombatAnimation)
+        .insert(
+            Tweener::new(Tween::delay(LUNGE_TIME)).then(
+                Tween::new(
+                    TweenTarget::Translation {
+                        start: start,
+                        end: start + Vec3::new(0.0, DAMAGE_NUMBER_RISE, 0.0),
+                    },
+                    DAMAGE_NUMBER_TIME,
+                    Ease::QuadOut,
+                )
+                .and(TweenTarget::Scale {
+                    start: Vec3::splat(DAMAGE_NUMBER_POP),
+                    end: Vec3::ONE,
+                })
+                .and(TweenTarget::TextColor {
+                    start: DAMAGE_NUMBER_COLOR,
+                    end: clear,
+                })
+                .with_event(ANIMATION_DONE),
+            ),
+        );
+}
+
+//Waits for the lunge to arrive, then wobbles side to side while flashing a brighter version of its color
+fn hit_tweener(origin: Vec3, color: Color) -> Tweener {
+    let step_time = HIT_TIME / HIT_STEPS as f32;
+    let strength = TILE_SIZE / 2.0;
+    let mut tweener = Tweener::new(Tween::delay(LUNGE_TIME));
+    let mut from = origin;
+    let mut from_tilt = Quat::IDENTITY;
+    for step in 0..HIT_STEPS {
+        let last = step + 1 == HIT_STEPS;
+        //Dies down towards the end and settles back where it started
+        let side = if step % 2 == 0 { 1.0 } else { -1.0 };
+        let falloff = 1.0 - step as f32 / HIT_STEPS as f32;
+        let (to, tilt) = if last {
+            (origin, Quat::IDENTITY)
+        } else {
+            (
+                origin + Vec3::new(side * strength * falloff, 0.0, 0.0),
+                Quat::from_rotation_z(side * HIT_TILT * falloff),
+            )
+        };
+        let flash = if step % 2 == 0 && !last {
+            flash_color(color)
+        } else {
+            color
+        };
+        let mut tween = Tween::new(
+            TweenTarget::Translation {
+                start: from,
+                end: to,
+            },
+            step_time,
+            Ease::SineInOut,
+        )
+        .and(TweenTarget::Rotation {
+            start: from_tilt,
+            end: tilt,
+        })
+        .and(TweenTarget::SpriteColor {
+            start: flash,
+            end: flash,
+        });
+        if last {
+            tween = tween.with_event(ANIMATION_DONE);
+        }
+        tweener = tweener.then(tween);
+        from = to;
+        from_tilt = tilt;
+    }
+    tweener
+}
+
+//Halfway to white so the flash keeps the sprite's own tint
+fn flash_color(color: Color) -> Color {
+    Color::rgba(
+        color.r() + (1.0 - color.r()) * 0.5,
+        color.g() + (1.0 - color.g()) * 0.5,
+        color.b() + (1.0 - color.b()) * 0.5,
+        color.a(),
+    )
+}
+
+fn finish_combat_animations(
+    mut commands: Commands,
+    mut completed: EventReader<TweenCompleted>,
+    number_query: Query<(), With<DamageNumber>>,
+) {
+    for event in completed
+        .iter()
+        .filter(|event| event.name == ANIMATION_DONE)
+    {
+        if number_query.get(event.entity).is_ok() {
+            commands.entity(event.entity).despawn_recursive();
+        } else {
+            commands.entity(event.entity).remove::<CombatAnimation>();
+        }
+    }
+}
+
+fn despawn_damage_numbers(mut commands: Commands, number_query: Query<Entity, With<DamageNumber>>) {
+    for entity in number_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
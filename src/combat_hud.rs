@@ -0,0 +1,243 @@
+use bevy::prelude::*;
+
+use crate::ascii::{
+    spawn_ascii_sprite, spawn_ascii_text, spawn_ascii_text_with_layout, AsciiSheet, TextAlign,
+    TextLayout, TextWidget,
+};
+use crate::camera::{Anchor, ScreenAnchor};
+use crate::combat::{Combatant, Health};
+use crate::combat_animation::CombatAnimation;
+use crate::font::FontId;
+use crate::nine_sprite::{spawn_nine_sprite, NineSpriteTheme};
+use crate::{GameState, TILE_SIZE};
+
+const BAR_CELLS: usize = 10;
+//Block glyphs on the ascii sheet
+const FULL_CELL: usize = 13 * 16 + 11;
+const HALF_CELL: usize = 13 * 16 + 13;
+const EMPTY_CELL: usize = 11 * 16;
+//Fraction of the whole bar drained per second
+const DRAIN_SPEED: f32 = 1.0;
+const HUD_WIDTH: f32 = 14.0 * TILE_SIZE;
+const HUD_HEIGHT: f32 = 5.0 * TILE_SIZE;
+
+pub struct CombatHudPlugin;
+
+impl Plugin for CombatHudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::Combat)
+                .with_system(start_draining.after("fight"))
+                .with_system(drain_hud_bars.after("fight")),
+        )
+        .add_system_set(SystemSet::on_exit(GameState::Combat).with_system(despawn_huds));
+    }
+}
+
+//Name, level, health bar and status for one side of the fight
+#[derive(Component)]
+pub struct CombatHud {
+    //Whoever's Health this shows
+    target: Entity,
+    //Eases toward the real health instead of jumping when hit
+    shown: f32,
+    goal: f32,
+    max: f32,
+    cells: Vec<Entity>,
+    numbers: Entity,
+}
+
+fn bar_color(percent: f32) -> Color {
+    if percent > 0.5 {
+        Color::GREEN
+    } else if percent > 0.2 {
+        Color::YELLOW
+    } else {
+        Color::RED
+    }
+}
+
+//Sprite index and colour for each cell of a bar showing health out of max
+fn bar_cells(health: f32, max: f32) -> Vec<(usize, Color)> {
+    let percent = if max > 0.0 { health / max } else { 0.0 };
+    let filled = percent * BAR_CELLS as f32;
+    (0..BAR_CELLS)
+        .map(|i| {
+            if filled >= i as f32 + 1.0 {
+                (FULL_CELL, bar_color(percent))
+            } else if filled >= i as f32 + 0.5 {
+                (HALF_CELL, bar_color(percent))
+            } else {
+                (EMPTY_CELL, Color::rgb(0.3, 0.3, 0.3))
+            }
+        })
+        .collect()
+}
+
+fn health_text(health: f32, max: f32) -> String {
+    //Round up so the number doesn't hit 0 before the bar is empty
+    let health = health.ceil() as i64;
+    let status = if health <= 0 { "{red}KO{/}" } else { "OK" };
+    format!("{:>3}/{:<3}  {}", health, max as i64, status)
+}
+
+//Top left corner goes corner away from the anchor point, z is kept as is
+pub fn spawn_combat_hud(
+    commands: &mut Commands,
+    ascii: &AsciiSheet,
+    target: Entity,
+    combatant: &Combatant,
+    health: &Health,
+    anchor: Anchor,
+    corner: Vec3,
+) -> Entity {
+    let theme = NineSpriteTheme {
+        shadow_color: Some(Color::rgba(0.0, 0.0, 0.0, 0.5)),
+        ..Default::default()
+    };
+    let mut children = vec![spawn_nine_sprite(
+        commands,
+        ascii.clone(),
+        theme,
+        HUD_WIDTH,
+        HUD_HEIGHT,
+    )];
+
+    let left = -HUD_WIDTH / 2.0 + 1.5 * TILE_SIZE;
+    let right = HUD_WIDTH / 2.0 - 1.5 * TILE_SIZE;
+    let top = HUD_HEIGHT / 2.0 - 1.5 * TILE_SIZE;
+    children.push(spawn_ascii_text_with_layout(
+        commands,
+        &combatant.name,
+        Vec3::new(left, top, 0.0),
+        TextLayout {
+            font: FontId::SmallBold,
+            ..Default::default()
+        },
+    ));
+    children.push(spawn_ascii_text_with_layout(
+        commands,
+        &format!("Lv{}", combatant.level),
+        Vec3::new(right, top, 0.0),
+        TextLayout {
+            align: TextAlign::Right,
+            ..Default::default()
+        },
+    ));
+
+    let bar_y = top - TILE_SIZE;
+    children.push(spawn_ascii_text(
+        commands,
+        "HP",
+        Vec3::new(left, bar_y, 0.0),
+    ));
+    let current = health.current as f32;
+    let max = health.max as f32;
+    let cells: Vec<Entity> = bar_cells(current, max)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (index, color))| {
+            let x = left + (i as f32 + 3.0) * TILE_SIZE;
+            spawn_ascii_sprite(
+                commands,
+                ascii,
+                index,
+                color,
+                Vec3::new(x, bar_y, 0.0),
+                Vec3::splat(1.0),
+            )
+        })
+        .collect();
+    children.extend(cells.iter());
+
+    let numbers = spawn_ascii_text(
+        commands,
+        &health_text(current, max),
+        Vec3::new(left + 2.0 * TILE_SIZE, bar_y - TILE_SIZE, 0.0),
+    );
+    children.push(numbers);
+
+    let center = corner + Vec3::new(HUD_WIDTH / 2.0, -HUD_HEIGHT / 2.0, 0.0);
+    commands
+        .spawn()
+        .insert(Name::new(format!("Hud - {}", combatant.name)))
+        .insert(CombatHud {
+            target: target,
+            shown: current,
+            goal: current,
+            max: max,
+            cells: cells,
+            numbers: numbers,
+        })
+        .insert(ScreenAnchor {
+            anchor: anchor,
+            offset: center.truncate(),
+        })
+        .insert(Transform::from_translation(center))
+        .insert(GlobalTransform::default())
+        .push_children(&children)
+        .id()
+}
+
+//Holds up the fight like any other animation until the bar finishes moving
+fn start_draining(
+    mut commands: Commands,
+    health_query: Query<(Entity, &Health), Changed<Health>>,
+    mut hud_query: Query<(Entity, &mut CombatHud)>,
+) {
+    for (target, health) in health_query.iter() {
+        for (entity, mut hud) in hud_query.iter_mut() {
+            if hud.target != target {
+                continue;
+            }
+            hud.goal = health.current as f32;
+            hud.max = health.max as f32;
+            if hud.shown != hud.goal {
+                commands.entity(entity).insert(CombatAnimation);
+            }
+        }
+    }
+}
+
+fn drain_hud_bars(
+    mut commands: Commands,
+    mut hud_query: Query<(Entity, &mut CombatHud)>,
+    mut sprite_query: Query<&mut TextureAtlasSprite>,
+    mut text_query: Query<&mut TextWidget>,
+    time: Res<Time>,
+) {
+    for (entity, mut hud) in hud_query.iter_mut() {
+        if hud.shown == hud.goal {
+            continue;
+        }
+        let step = DRAIN_SPEED * hud.max * time.delta_seconds();
+        hud.shown = if hud.shown > hud.goal {
+            (hud.shown - step).max(hud.goal)
+        } else {
+            (hud.shown + step).min(hud.goal)
+        };
+
+        for (cell, (index, color)) in hud.cells.iter().zip(bar_cells(hud.shown, hud.max)) {
+            if let Ok(mut sprite) = sprite_query.get_mut(*cell) {
+                sprite.index = index;
+                sprite.color = color;
+            }
+        }
+        if let Ok(mut text) = text_query.get_mut(hud.numbers) {
+            let numbers = health_text(hud.shown, hud.max);
+            if text.text != numbers {
+                text.text = numbers;
+            }
+        }
+
+        if hud.shown == hud.goal {
+            commands.entity(entity).remove::<CombatAnimation>();
+        }
+    }
+}
+
+fn despawn_huds(mut commands: Commands, hud_query: Query<Entity, With<CombatHud>>) {
+    for entity in hud_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,167 @@
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+use crate::ascii::{spawn_ascii_text_with_layout, AsciiSheet, TextLayout, TextWidget};
+use crate::camera::{Anchor, ScreenAnchor};
+use crate::font::FontId;
+use crate::input::GameAction;
+use crate::nine_sprite::{spawn_nine_sprite, NineSpriteTheme};
+use crate::settings::Settings;
+use crate::{GameState, TILE_SIZE};
+
+//How long a fully shown message waits before moving on by itself
+const AUTO_ADVANCE_TIME: f32 = 1.5;
+const BOX_WIDTH: f32 = 22.0 * TILE_SIZE;
+const BOX_HEIGHT: f32 = 4.0 * TILE_SIZE;
+
+pub struct CombatLogPlugin;
+
+impl Plugin for CombatLogPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CombatLog>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Combat)
+                    .with_system(show_combat_log.label("combat_log")),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Combat).with_system(clear_combat_log));
+    }
+}
+
+//Messages waiting to be shown, the front one is on screen
+//Combat waits for this to empty before moving on to anything else
+#[derive(Default)]
+pub struct CombatLog {
+    messages: VecDeque<String>,
+}
+
+impl CombatLog {
+    pub fn push(&mut self, message: &str) {
+        self.messages.push_back(message.to_string());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+#[derive(Component)]
+struct MessageBox {
+    text: Entity,
+    //How many characters of the current message are showing
+    revealed: f32,
+    //Starts once the whole message is showing
+    auto_advance: Timer,
+}
+
+fn spawn_message_box(commands: &mut Commands, ascii: &AsciiSheet) {
+    let theme = NineSpriteTheme {
+        shadow_color: Some(Color::rgba(0.0, 0.0, 0.0, 0.5)),
+        ..Default::default()
+    };
+    let frame = spawn_nine_sprite(commands, ascii.clone(), theme, BOX_WIDTH, BOX_HEIGHT);
+    let text = spawn_ascii_text_with_layout(
+        commands,
+        "",
+        Vec3::new(-BOX_WIDTH / 2.0 + 1.5 * TILE_SIZE, 0.5 * TILE_SIZE, 0.0),
+        TextLayout {
+            max_width: Some((BOX_WIDTH / TILE_SIZE) as usize - 3),
+            font: FontId::Small,
+            ..Default::default()
+        },
+    );
+
+    //Right side of the screen between the enemy and the menu, clear of the player's monster
+    let offset = Vec2::new(-TILE_SIZE - BOX_WIDTH / 2.0, 0.0);
+    commands
+        .spawn()
+        .insert(Name::new("MessageBox"))
+        .insert(MessageBox {
+            text: text,
+            revealed: 0.0,
+            auto_advance: Timer::from_seconds(AUTO_ADVANCE_TIME, false),
+        })
+        .insert(ScreenAnchor {
+            anchor: Anchor::Right,
+            offset: offset,
+        })
+        .insert(Transform::from_translation(offset.extend(10.0)))
+        .insert(GlobalTransform::default())
+        .push_children(&[frame, text]);
+}
+
+//Types out the front message, Confirm skips the typing or moves to the next message
+#[allow(clippy::too_many_arguments)]
+fn show_combat_log(
+    mut commands: Commands,
+    mut log: ResMut<CombatLog>,
+    mut box_query: Query<(Entity, &mut MessageBox)>,
+    mut text_query: Query<&mut TextWidget>,
+    actions: Res<Input<GameAction>>,
+    ascii: Res<AsciiSheet>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+) {
+    let (entity, mut message_box) = match box_query.get_single_mut() {
+        Ok(message_box) => message_box,
+        Err(_) => {
+            if !log.is_empty() {
+                spawn_message_box(&mut commands, &ascii);
+            }
+            return;
+        }
+    };
+    let message = match log.messages.front() {
+        Some(message) => message,
+        None => {
+            commands.entity(entity).despawn_recursive();
+            return;
+        }
+    };
+
+    let length = message.chars().count() as f32;
+    let fully_shown = message_box.revealed >= length;
+    let mut advance = false;
+    if actions.just_pressed(GameAction::Confirm) {
+        if fully_shown {
+            advance = true;
+        } else {
+            message_box.revealed = length;
+        }
+    } else if fully_shown {
+        advance = message_box.auto_advance.tick(time.delta()).finished();
+    } else {
+        message_box.revealed = match settings.text_speed.chars_per_second() {
+            Some(speed) => message_box.revealed + speed * time.delta_seconds(),
+            None => length,
+        };
+    }
+
+    //Messages are plain text so any { is escaped instead of read as markup
+    let shown: String = message
+        .chars()
+        .take(message_box.revealed as usize)
+        .collect::<String>()
+        .replace('{', "{{");
+    if let Ok(mut text) = text_query.get_mut(message_box.text) {
+        if text.text != shown {
+            text.text = shown;
+        }
+    }
+
+    if advance {
+        log.messages.pop_front();
+        message_box.revealed = 0.0;
+        message_box.auto_advance.reset();
+    }
+}
+
+fn clear_combat_log(
+    mut commands: Commands,
+    mut log: ResMut<CombatLog>,
+    box_query: Query<Entity, With<MessageBox>>,
+) {
+    log.messages.clear();
+    for entity in box_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -2,15 +2,16 @@ use bevy::prelude::*;
 use bevy_inspector_egui::Inspectable;
 use rand::{thread_rng, Rng};
 
-//FIXME this is a horrible
-pub const HEALTH_UI_ID: usize = 2;
-
 use crate::{
-    ascii::{spawn_ascii_sprite, spawn_ascii_text, AsciiSheet},
+    ascii::{spawn_ascii_sprite, AsciiSheet},
+    camera::Anchor,
+    combat::{Combatant, Health},
+    combat_hud::spawn_combat_hud,
+    combat_log::CombatLog,
     TILE_SIZE,
 };
 
-#[derive(Inspectable, Copy, Clone)]
+#[derive(Inspectable, Debug, Copy, Clone, PartialEq)]
 pub enum EnemyType {
     Bat,
     Zombie,
@@ -19,63 +20,112 @@ pub enum EnemyType {
     Giant,
 }
 
+const ALL_ENEMY_TYPES: [EnemyType; 5] = [
+    EnemyType::Bat,
+    EnemyType::Zombie,
+    EnemyType::Ghost,
+    EnemyType::Demon,
+    EnemyType::Giant,
+];
+
 #[derive(Component, Inspectable, Copy, Clone)]
 pub struct Enemy {
     enemy_type: EnemyType,
-    sprite_index: usize,
-    color: Color,
-    pub health: i64,
+    pub sprite_index: usize,
+    pub color: Color,
+    pub max_health: i64,
+    pub level: i64,
     exp: i64,
 }
 
-fn get_random_enemy() -> Enemy {
-    let mut rng = thread_rng();
-    //TODO weighted odds
-    let rand = rng.gen_range(0..5);
-    match rand {
-        0 => Enemy {
+//The enemy's glyph, a child of the Enemy
+#[derive(Component)]
+pub struct EnemySprite;
+
+//Which enemy the next fight is against, set right before entering combat
+pub struct Encounter {
+    pub enemy_type: EnemyType,
+    pub level: i64,
+}
+
+impl Default for Encounter {
+    fn default() -> Self {
+        Encounter {
             enemy_type: EnemyType::Bat,
-            sprite_index: 'b' as usize,
-            health: 3,
-            color: Color::rgb(0.6, 0.6, 0.6),
-            exp: 3,
-        },
-        1 => Enemy {
-            enemy_type: EnemyType::Zombie,
-            sprite_index: 'Z' as usize,
-            health: 5,
-            color: Color::rgb(0.6, 1.0, 0.6),
-            exp: 7,
-        },
-        2 => Enemy {
-            enemy_type: EnemyType::Ghost,
-            sprite_index: 'g' as usize,
-            health: 6,
-            color: Color::rgb(0.9, 0.9, 0.9),
-            exp: 7,
-        },
-        3 => Enemy {
-            enemy_type: EnemyType::Demon,
-            sprite_index: 'D' as usize,
-            health: 10,
-            color: Color::rgb(0.9, 0.2, 0.2),
-            exp: 15,
-        },
-        4 => Enemy {
-            enemy_type: EnemyType::Giant,
-            sprite_index: 'G' as usize,
-            health: 20,
-            color: Color::rgb(0.1, 0.5, 0.1),
-            exp: 45,
-        },
-        _ => {
-            unreachable!("Bad enemy roll");
+            level: 1,
         }
     }
 }
 
-pub fn create_enemy(mut commands: Commands, ascii: Res<AsciiSheet>) {
-    let enemy = get_random_enemy();
+impl Enemy {
+    //Stats are for level 1, health and exp grow with level
+    pub fn new(enemy_type: EnemyType, level: i64) -> Enemy {
+        let mut enemy = match enemy_type {
+            EnemyType::Bat => Enemy {
+                enemy_type: EnemyType::Bat,
+                sprite_index: 'b' as usize,
+                max_health: 3,
+                color: Color::rgb(0.6, 0.6, 0.6),
+                level: level,
+                exp: 3,
+            },
+            EnemyType::Zombie => Enemy {
+                enemy_type: EnemyType::Zombie,
+                sprite_index: 'Z' as usize,
+                max_health: 5,
+                color: Color::rgb(0.6, 1.0, 0.6),
+                level: level,
+                exp: 7,
+            },
+            EnemyType::Ghost => Enemy {
+                enemy_type: EnemyType::Ghost,
+                sprite_index: 'g' as usize,
+                max_health: 6,
+                color: Color::rgb(0.9, 0.9, 0.9),
+                level: level,
+                exp: 7,
+            },
+            EnemyType::Demon => Enemy {
+                enemy_type: EnemyType::Demon,
+                sprite_index: 'D' as usize,
+                max_health: 10,
+                color: Color::rgb(0.9, 0.2, 0.2),
+                level: level,
+                exp: 15,
+            },
+            EnemyType::Giant => Enemy {
+                enemy_type: EnemyType::Giant,
+                sprite_index: 'G' as usize,
+                max_health: 20,
+                color: Color::rgb(0.1, 0.5, 0.1),
+                level: level,
+                exp: 45,
+            },
+        };
+        enemy.max_health += (level - 1) * 2;
+        enemy.exp *= level;
+        enemy
+    }
+}
+
+pub fn random_enemy_type() -> EnemyType {
+    let mut rng = thread_rng();
+    //TODO weighted odds
+    ALL_ENEMY_TYPES[rng.gen_range(0..ALL_ENEMY_TYPES.len())]
+}
+
+pub fn create_enemy(
+    mut commands: Commands,
+    ascii: Res<AsciiSheet>,
+    encounter: Res<Encounter>,
+    mut log: ResMut<CombatLog>,
+) {
+    let enemy = Enemy::new(encounter.enemy_type, encounter.level);
+    let combatant = Combatant {
+        name: format!("{:?}", enemy.enemy_type),
+        level: enemy.level,
+    };
+    let health = Health::new(enemy.max_health);
 
     let sprite = spawn_ascii_sprite(
         &mut commands,
@@ -86,21 +136,28 @@ pub fn create_enemy(mut commands: Commands, ascii: Res<AsciiSheet>) {
         Vec3::new(0.0, 0.5, 1.0),
         Vec3::splat(3.0),
     );
-    //TODO center
-    let health_bar = spawn_ascii_text(
-        &mut commands,
-        ascii.clone(),
-        &format!("Health: {}", enemy.health),
-        Vec3::new(-0.5, 1.0 - 2.0 * TILE_SIZE, 1.0),
-        HEALTH_UI_ID,
-    );
-    commands
+    commands.entity(sprite).insert(EnemySprite);
+    let entity = commands
         .spawn()
         .insert(enemy)
+        .insert(health)
         .insert(Name::new("Enemy"))
         .insert(Transform::default())
         .insert(GlobalTransform::default())
-        .push_children(&[sprite, health_bar]);
+        .push_children(&[sprite])
+        .id();
+    //Top left of the screen
+    spawn_combat_hud(
+        &mut commands,
+        &ascii,
+        entity,
+        &combatant,
+        &health,
+        Anchor::TopLeft,
+        Vec3::new(TILE_SIZE, -TILE_SIZE, 5.0),
+    );
+    log.push(&format!("A wild {} appeared!", combatant.name));
+    commands.entity(entity).insert(combatant);
 }
 
 pub fn destroy_enemy(mut commands: Commands, enemy_query: Query<Entity, With<Enemy>>) {
@@ -0,0 +1,210 @@
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::sprite::Rect;
+use bevy::utils::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::TILE_SIZE;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum FontId {
+    //Ascii.png, every character is one tile wide
+    Ascii,
+    //Titles
+    Large,
+    //Fits more on a line, used for longer messages
+    Small,
+    SmallBold,
+}
+
+impl Default for FontId {
+    fn default() -> Self {
+        FontId::Ascii
+    }
+}
+
+const FONT_FILES: [(FontId, &str); 4] = [
+    (FontId::Ascii, "assets/ascii.font"),
+    (FontId::Large, "assets/fontlarge.font"),
+    (FontId::Small, "assets/fontsmall1.font"),
+    (FontId::SmallBold, "assets/fontsmall2.font"),
+];
+
+//Everything in here is in pixels of the font's image
+#[derive(Clone, Copy)]
+pub struct GlyphInfo {
+    //Into the font's texture atlas
+    pub index: usize,
+    pub size: Vec2,
+    //How far the bottom of the glyph sits above the baseline, negative for descenders
+    pub bottom: f32,
+    //How far the next character starts from this one
+    pub advance: f32,
+}
+
+#[derive(TypeUuid)]
+#[uuid = "0c6a2e4d-8b7f-4f19-a3d2-6e5b1c9f7a40"]
+pub struct BitmapFont {
+    pub atlas: Handle<TextureAtlas>,
+    //Distance between lines, always drawn as one tile
+    pub line_height: f32,
+    //Down from the top of a line
+    pub baseline: f32,
+    //Used when the font doesn't draw a space
+    space: f32,
+    glyphs: HashMap<char, GlyphInfo>,
+}
+
+impl BitmapFont {
+    //Size of one of the font's pixels on screen
+    pub fn pixel_size(&self) -> f32 {
+        TILE_SIZE / self.line_height
+    }
+
+    pub fn glyph(&self, char: char) -> Option<GlyphInfo> {
+        self.glyphs.get(&char).copied()
+    }
+
+    //Fonts without a space glyph still need the gap between words
+    pub fn space(&self) -> f32 {
+        self.glyph(' ').map_or(self.space, |glyph| glyph.advance)
+    }
+}
+
+//Every font the game can draw text in, loaded once at startup
+pub struct Fonts(HashMap<FontId, Handle<BitmapFont>>);
+
+impl Fonts {
+    pub fn get(&self, id: FontId) -> &Handle<BitmapFont> {
+        &self.0[&id]
+    }
+}
+
+pub fn load_fonts(
+    assets: &AssetServer,
+    texture_atlases: &mut Assets<TextureAtlas>,
+    fonts: &mut Assets<BitmapFont>,
+) -> Fonts {
+    let mut handles = HashMap::default();
+    for (id, path) in FONT_FILES {
+        let font = load_font(assets, texture_atlases, Path::new(path));
+        handles.insert(id, fonts.add(font));
+    }
+    Fonts(handles)
+}
+
+//Font files list the image first, then either a grid of glyphs or one line per glyph
+//Bad lines are skipped so one typo doesn't lose the whole font
+fn load_font(
+    assets: &AssetServer,
+    texture_atlases: &mut Assets<TextureAtlas>,
+    path: &Path,
+) -> BitmapFont {
+    let input = File::open(path).expect("No font found");
+    let mut image = None;
+    let mut atlas = None;
+    let mut font = BitmapFont {
+        atlas: Handle::default(),
+        line_height: 1.0,
+        baseline: 1.0,
+        space: 0.0,
+        glyphs: HashMap::default(),
+    };
+
+    for line in BufReader::new(input).lines().flatten() {
+        if line.starts_with('/') || line.trim().is_empty() {
+            continue;
+        }
+        if !parse_font_line(&line, assets, &mut image, &mut atlas, &mut font) {
+            println!("Bad font formatting in {}: {}", path.display(), line);
+        }
+    }
+
+    let atlas = atlas.expect("Font has no size or grid");
+    font.atlas = texture_atlases.add(atlas);
+    font
+}
+
+fn parse_font_line(
+    line: &str,
+    assets: &AssetServer,
+    image: &mut Option<Handle<Image>>,
+    atlas: &mut Option<TextureAtlas>,
+    font: &mut BitmapFont,
+) -> bool {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let numbers: Vec<f32> = words[1..]
+        .iter()
+        .filter_map(|word| word.parse().ok())
+        .collect();
+
+    match (words[0], numbers.len()) {
+        ("image", _) if words.len() == 2 => *image = Some(assets.load(words[1])),
+        ("line_height", 1) => font.line_height = numbers[0],
+        ("baseline", 1) => font.baseline = numbers[0],
+        ("space", 1) => font.space = numbers[0],
+        //Glyphs are added one at a time after this
+        ("size", 2) => {
+            *atlas = Some(TextureAtlas::new_empty(
+                image.clone().unwrap_or_default(),
+                Vec2::new(numbers[0], numbers[1]),
+            ));
+        }
+        //columns rows cell padding, each cell is the character with that code
+        ("grid", 4) => {
+            let cell = numbers[2];
+            *atlas = Some(TextureAtlas::from_grid_with_padding(
+                image.clone().unwrap_or_default(),
+                Vec2::splat(cell),
+                numbers[0] as usize,
+                numbers[1] as usize,
+                Vec2::splat(numbers[3]),
+            ));
+            for index in 0..(numbers[0] * numbers[1]) as u32 {
+                if let Some(char) = char::from_u32(index) {
+                    font.glyphs.insert(
+                        char,
+                        GlyphInfo {
+                            index: index as usize,
+                            size: Vec2::splat(cell),
+                            bottom: 0.0,
+                            advance: cell,
+                        },
+                    );
+                }
+            }
+        }
+        //char x y width height bottom advance
+        ("glyph", _) if words.len() == 8 && words[1].chars().count() == 1 => {
+            //The character itself might be a digit so it's parsed separately
+            let numbers: Vec<f32> = words[2..]
+                .iter()
+                .filter_map(|word| word.parse().ok())
+                .collect();
+            let atlas = match atlas {
+                Some(atlas) if numbers.len() == 6 => atlas,
+                _ => return false,
+            };
+            let min = Vec2::new(numbers[0], numbers[1]);
+            let size = Vec2::new(numbers[2], numbers[3]);
+            let index = atlas.len();
+            atlas.add_texture(Rect {
+                min: min,
+                max: min + size,
+            });
+            font.glyphs.insert(
+                words[1].chars().next().unwrap(),
+                GlyphInfo {
+                    index: index,
+                    size: size,
+                    bottom: numbers[4],
+                    advance: numbers[5],
+                },
+            );
+        }
+        _ => return false,
+    }
+    true
+}
@@ -0,0 +1,126 @@
+use bevy::ecs::schedule::ShouldRun;
+use bevy::prelude::*;
+use bevy::utils::{Duration, Instant};
+use std::thread;
+
+use crate::settings::Settings;
+
+//Movement always advances by this much no matter how fast frames come in
+pub const FIXED_TIMESTEP: f64 = 1.0 / 60.0;
+//Time past this many steps in one frame is dropped, so after a long stall (dragging the
+//window, a breakpoint) the game slows down for a moment instead of fast forwarding
+const MAX_STEPS_PER_FRAME: u32 = 5;
+
+//Runs right before Update, zero or more times a frame to keep up with real time
+#[derive(Debug, Clone, PartialEq, Eq, Hash, StageLabel)]
+pub struct FixedUpdate;
+
+pub struct FramePacingPlugin;
+
+impl Plugin for FramePacingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_stage_before(
+            CoreStage::Update,
+            FixedUpdate,
+            SystemStage::parallel().with_run_criteria(fixed_step),
+        )
+        //Exclusive and last so the wait happens on the main thread once everything else is done
+        .add_system_to_stage(
+            CoreStage::Last,
+            limit_frame_rate.exclusive_system().at_end(),
+        );
+    }
+}
+
+//Like bevy's FixedTimestep but with a limit on how many steps one frame can run
+#[derive(Default)]
+struct FixedSteps {
+    accumulator: f64,
+    steps_this_frame: u32,
+    //Set while the stage is being rerun within the same frame
+    looping: bool,
+}
+
+impl FixedSteps {
+    fn should_run(&mut self, delta: f64) -> ShouldRun {
+        if !self.looping {
+            self.accumulator += delta;
+            self.steps_this_frame = 0;
+        }
+        if self.accumulator >= FIXED_TIMESTEP && self.steps_this_frame < MAX_STEPS_PER_FRAME {
+            self.accumulator -= FIXED_TIMESTEP;
+            self.steps_this_frame += 1;
+            self.looping = true;
+            ShouldRun::YesAndCheckAgain
+        } else {
+            //Whole steps that didn't fit are dropped, the part of a step left over is kept
+            self.accumulator %= FIXED_TIMESTEP;
+            self.looping = false;
+            ShouldRun::No
+        }
+    }
+}
+
+fn fixed_step(time: Res<Time>, mut steps: Local<FixedSteps>) -> ShouldRun {
+    steps.should_run(time.delta_seconds_f64())
+}
+
+//Waits out whatever is left of the frame, time spent updating and rendering already counts
+//Keeps laptops from hitting 400fps and using 100% cpu
+// https://github.com/bevyengine/bevy/issues/1343
+fn limit_frame_rate(settings: Res<Settings>, mut frame_start: Local<Option<Instant>>) {
+    let now = Instant::now();
+    let cap = match settings.frame_cap {
+        Some(cap) if cap > 0 => cap,
+        _ => {
+            *frame_start = Some(now);
+            return;
+        }
+    };
+    let target = match *frame_start {
+        Some(start) => start + Duration::from_secs_f64(1.0 / cap as f64),
+        None => now,
+    };
+    if now < target {
+        thread::sleep(target - now);
+    }
+    //A slow frame starts the next one fresh instead of rushing to catch up
+    *frame_start = Some(target.max(now));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //How many times the stage runs in a frame of this length
+    fn steps_in_frame(steps: &mut FixedSteps, delta: f64) -> u32 {
+        let mut count = 0;
+        let mut delta = delta;
+        while steps.should_run(delta) == ShouldRun::YesAndCheckAgain {
+            count += 1;
+            //Only the first check of a frame adds time
+            delta = 0.0;
+        }
+        count
+    }
+
+    #[test]
+    fn leftover_time_carries_into_the_next_frame() {
+        let mut steps = FixedSteps::default();
+        assert_eq!(steps_in_frame(&mut steps, FIXED_TIMESTEP * 0.75), 0);
+        assert_eq!(steps_in_frame(&mut steps, FIXED_TIMESTEP * 0.75), 1);
+        assert_eq!(steps_in_frame(&mut steps, FIXED_TIMESTEP * 2.0), 2);
+    }
+
+    #[test]
+    fn long_frames_are_capped_and_the_rest_dropped() {
+        let mut steps = FixedSteps::default();
+        assert_eq!(
+            steps_in_frame(&mut steps, FIXED_TIMESTEP * 10.5),
+            MAX_STEPS_PER_FRAME
+        );
+        //Only the half step is left to catch up on
+        assert_eq!(steps_in_frame(&mut steps, FIXED_TIMESTEP * 0.25), 0);
+        assert_eq!(steps_in_frame(&mut steps, FIXED_TIMESTEP * 0.75), 1);
+    }
+}
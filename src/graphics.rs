@@ -1,27 +1,35 @@
 use bevy::prelude::*;
+use bevy::utils::HashMap;
 use bevy_inspector_egui::Inspectable;
 
-use crate::{debug::ENABLE_INSPECTOR, player::Player};
+use crate::animation::{AnimationClip, Animator, PlaybackMode};
+
+const CHARACTER_COUNT: usize = 8;
+const FIDGET_CLIP: &str = "fidget";
 
 pub struct GraphicsPlugin;
 
 impl Plugin for GraphicsPlugin {
     fn build(&self, app: &mut App) {
         app.add_startup_system_to_stage(StartupStage::PreStartup, load_graphics)
-            .add_system(animate_player)
-            .add_system(animate_sprites);
-        if ENABLE_INSPECTOR {
-            app.register_type::<AnimatedSprite>();
-        }
+            .add_system(select_character_clips.before("animate_sprites"));
     }
 }
 
 pub struct GraphicsHandles {
     pub characters: Handle<TextureAtlas>,
     pub tiles: Handle<TextureAtlas>,
+    //Walk, idle and fidget clips for each character in characters.png
+    character_clips: Vec<HashMap<String, Handle<AnimationClip>>>,
 }
 
-#[derive(Component, Inspectable)]
+impl GraphicsHandles {
+    pub fn character_animator(&self, character: usize) -> Animator {
+        Animator::new(self.character_clips[character].clone(), "idle_down")
+    }
+}
+
+#[derive(Component, Inspectable, Clone, Copy, PartialEq)]
 pub enum FacingDirection {
     Up,
     Down,
@@ -29,24 +37,98 @@ pub enum FacingDirection {
     Right,
 }
 
-pub struct PlayerAnimations {
-    pub walk_down: Vec<usize>,
-    pub walk_up: Vec<usize>,
-    pub walk_left: Vec<usize>,
-    pub walk_right: Vec<usize>,
+//Set by whatever moves a character, picks between the walk and idle clips
+#[derive(Component, Default)]
+pub struct Walking(pub bool);
+
+//Optional, plays the fidget clip after standing still for a while
+#[derive(Component)]
+pub struct IdleFidget {
+    timer: Timer,
+}
+
+impl IdleFidget {
+    pub fn new(delay: f32) -> IdleFidget {
+        IdleFidget {
+            timer: Timer::from_seconds(delay, false),
+        }
+    }
 }
 
-#[derive(Component, Reflect, Default)]
-#[reflect(Component)]
-pub struct AnimatedSprite {
-    pub current_frame: usize,
-    pub timer: Timer,
+//characters.png is 4 characters wide and 2 tall, each one is 3 frames wide and
+//has a row per direction in the order down, left, right, up
+fn character_frames(character: usize, direction: FacingDirection) -> Vec<usize> {
+    let column = (character % 4) * 3;
+    let direction_row = match direction {
+        FacingDirection::Down => 0,
+        FacingDirection::Left => 1,
+        FacingDirection::Right => 2,
+        FacingDirection::Up => 3,
+    };
+    let row = (character / 4) * 4 + direction_row;
+    (0..3).map(|frame| row * 12 + column + frame).collect()
+}
+
+fn clip_name(walking: bool, direction: FacingDirection) -> &'static str {
+    match (walking, direction) {
+        (true, FacingDirection::Up) => "walk_up",
+        (true, FacingDirection::Down) => "walk_down",
+        (true, FacingDirection::Left) => "walk_left",
+        (true, FacingDirection::Right) => "walk_right",
+        (false, FacingDirection::Up) => "idle_up",
+        (false, FacingDirection::Down) => "idle_down",
+        (false, FacingDirection::Left) => "idle_left",
+        (false, FacingDirection::Right) => "idle_right",
+    }
+}
+
+fn load_character_clips(
+    clips: &mut Assets<AnimationClip>,
+    character: usize,
+) -> HashMap<String, Handle<AnimationClip>> {
+    let mut handles = HashMap::default();
+    //Looks left and right then back down using the standing frames, lingering on each side
+    let fidget_frames = [
+        FacingDirection::Down,
+        FacingDirection::Left,
+        FacingDirection::Down,
+        FacingDirection::Right,
+        FacingDirection::Down,
+    ]
+    .iter()
+    .map(|direction| character_frames(character, *direction)[1])
+    .collect();
+    let fidget = AnimationClip::with_durations(
+        fidget_frames,
+        vec![0.3, 0.7, 0.3, 0.7, 0.5],
+        PlaybackMode::Once,
+    );
+    handles.insert(FIDGET_CLIP.to_string(), clips.add(fidget));
+
+    for direction in [
+        FacingDirection::Up,
+        FacingDirection::Down,
+        FacingDirection::Left,
+        FacingDirection::Right,
+    ] {
+        let frames = character_frames(character, direction);
+        //Middle frame is the standing pose
+        let idle = AnimationClip::new(vec![frames[1]], 1.0, PlaybackMode::Loop);
+        //Feet hit the ground on the outer frames
+        let walk = AnimationClip::new(frames, 0.2, PlaybackMode::PingPong)
+            .with_event(0, "footstep")
+            .with_event(2, "footstep");
+        handles.insert(clip_name(false, direction).to_string(), clips.add(idle));
+        handles.insert(clip_name(true, direction).to_string(), clips.add(walk));
+    }
+    handles
 }
 
 fn load_graphics(
     mut commands: Commands,
     assets: Res<AssetServer>,
     mut atlases: ResMut<Assets<TextureAtlas>>,
+    mut clips: ResMut<Assets<AnimationClip>>,
 ) {
     let image = assets.load("characters.png");
     let atlas =
@@ -58,53 +140,43 @@ fn load_graphics(
         TextureAtlas::from_grid_with_padding(image, Vec2::splat(16.0), 8, 15, Vec2::splat(2.0));
     let tile_handle = atlases.add(atlas);
 
+    let character_clips = (0..CHARACTER_COUNT)
+        .map(|character| load_character_clips(&mut clips, character))
+        .collect();
+
     commands.insert_resource(GraphicsHandles {
         characters: character_handle,
         tiles: tile_handle,
-    });
-
-    commands.insert_resource(PlayerAnimations {
-        walk_down: vec![3, 4, 5],
-        walk_up: vec![39, 40, 41],
-        walk_left: vec![15, 16, 17],
-        walk_right: vec![27, 28, 29],
+        character_clips: character_clips,
     });
 }
 
-//TODO restructure this to support animations more generally
-fn animate_sprites(mut sprites: Query<&mut AnimatedSprite>, time: Res<Time>) {
-    for mut sprite in sprites.iter_mut() {
-        sprite.timer.tick(time.delta());
-        if sprite.timer.just_finished() {
-            //Probs not dangerous but
-            //FIXME handle wrap around
-            sprite.current_frame += 1;
-        }
-    }
-}
-
-fn animate_player(
-    mut player_query: Query<(&mut TextureAtlasSprite, &AnimatedSprite, &Player)>,
-    animations: Res<PlayerAnimations>,
+fn select_character_clips(
+    mut character_query: Query<(
+        &mut Animator,
+        &FacingDirection,
+        &Walking,
+        Option<&mut IdleFidget>,
+    )>,
+    time: Res<Time>,
 ) {
-    let (mut sprite, animated_sprite, player) = player_query.single_mut();
-    //FIXME clean this up
-    match player.current_direction {
-        FacingDirection::Up => {
-            sprite.index =
-                animations.walk_up[animated_sprite.current_frame % animations.walk_up.len()];
-        }
-        FacingDirection::Down => {
-            sprite.index =
-                animations.walk_down[animated_sprite.current_frame % animations.walk_down.len()];
-        }
-        FacingDirection::Left => {
-            sprite.index =
-                animations.walk_left[animated_sprite.current_frame % animations.walk_left.len()];
-        }
-        FacingDirection::Right => {
-            sprite.index =
-                animations.walk_right[animated_sprite.current_frame % animations.walk_right.len()];
+    for (mut animator, direction, walking, fidget) in character_query.iter_mut() {
+        if let Some(mut fidget) = fidget {
+            if walking.0 {
+                fidget.timer.reset();
+            } else {
+                fidget.timer.tick(time.delta());
+                if fidget.timer.finished() {
+                    if animator.is_playing(FIDGET_CLIP) && animator.finished() {
+                        //Back to standing, wait for the next fidget
+                        fidget.timer.reset();
+                    } else {
+                        animator.play(FIDGET_CLIP);
+                        continue;
+                    }
+                }
+            }
         }
+        animator.play(clip_name(walking.0, *direction));
     }
 }
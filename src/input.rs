@@ -0,0 +1,536 @@
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+pub const CONTROLS_PATH: &str = "controls.txt";
+
+//How far a stick has to be pushed before it counts as a press
+const STICK_DEADZONE: f32 = 0.5;
+
+//Logical buttons, everything outside this file should read Res<Input<GameAction>>
+//instead of checking keys or gamepad buttons directly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameAction {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Confirm,
+    Cancel,
+    Menu,
+    ToggleMovement,
+    VolumeUp,
+    VolumeDown,
+}
+
+pub const ALL_ACTIONS: [GameAction; 10] = [
+    GameAction::MoveUp,
+    GameAction::MoveDown,
+    GameAction::MoveLeft,
+    GameAction::MoveRight,
+    GameAction::Confirm,
+    GameAction::Cancel,
+    GameAction::Menu,
+    GameAction::ToggleMovement,
+    GameAction::VolumeUp,
+    GameAction::VolumeDown,
+];
+
+//Only these keys can be loaded back from the controls file, so nothing else can be bound
+const BINDABLE_KEYS: [KeyCode; 60] = [
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Return,
+    KeyCode::Escape,
+    KeyCode::Space,
+    KeyCode::Tab,
+    KeyCode::Back,
+    KeyCode::LShift,
+    KeyCode::RShift,
+    KeyCode::LControl,
+    KeyCode::RControl,
+    KeyCode::LAlt,
+    KeyCode::RAlt,
+    KeyCode::Comma,
+    KeyCode::Period,
+    KeyCode::Slash,
+    KeyCode::Semicolon,
+    KeyCode::Apostrophe,
+    KeyCode::Minus,
+    KeyCode::Equals,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+];
+
+const BINDABLE_BUTTONS: [GamepadButtonType; 19] = [
+    GamepadButtonType::South,
+    GamepadButtonType::East,
+    GamepadButtonType::North,
+    GamepadButtonType::West,
+    GamepadButtonType::C,
+    GamepadButtonType::Z,
+    GamepadButtonType::LeftTrigger,
+    GamepadButtonType::LeftTrigger2,
+    GamepadButtonType::RightTrigger,
+    GamepadButtonType::RightTrigger2,
+    GamepadButtonType::Select,
+    GamepadButtonType::Start,
+    GamepadButtonType::Mode,
+    GamepadButtonType::LeftThumb,
+    GamepadButtonType::RightThumb,
+    GamepadButtonType::DPadUp,
+    GamepadButtonType::DPadDown,
+    GamepadButtonType::DPadLeft,
+    GamepadButtonType::DPadRight,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    Button(GamepadButtonType),
+}
+
+//Which keys and gamepad buttons trigger each action
+#[derive(Clone)]
+pub struct InputMap {
+    bindings: HashMap<GameAction, Vec<Binding>>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let mut map = InputMap {
+            bindings: HashMap::default(),
+        };
+        let defaults = [
+            (GameAction::MoveUp, KeyCode::W, GamepadButtonType::DPadUp),
+            (
+                GameAction::MoveDown,
+                KeyCode::S,
+                GamepadButtonType::DPadDown,
+            ),
+            (
+                GameAction::MoveLeft,
+                KeyCode::A,
+                GamepadButtonType::DPadLeft,
+            ),
+            (
+                GameAction::MoveRight,
+                KeyCode::D,
+                GamepadButtonType::DPadRight,
+            ),
+            (
+                GameAction::Confirm,
+                KeyCode::Return,
+                GamepadButtonType::South,
+            ),
+            (GameAction::Cancel, KeyCode::Escape, GamepadButtonType::East),
+            (GameAction::Menu, KeyCode::Escape, GamepadButtonType::Start),
+            (
+                GameAction::ToggleMovement,
+                KeyCode::Tab,
+                GamepadButtonType::Select,
+            ),
+            (
+                GameAction::VolumeUp,
+                KeyCode::Up,
+                GamepadButtonType::RightTrigger,
+            ),
+            (
+                GameAction::VolumeDown,
+                KeyCode::Down,
+                GamepadButtonType::LeftTrigger,
+            ),
+        ];
+        for (action, key, button) in defaults {
+            map.bindings
+                .insert(action, vec![Binding::Key(key), Binding::Button(button)]);
+        }
+        map
+    }
+}
+
+impl InputMap {
+    pub fn bindings(&self, action: GameAction) -> &[Binding] {
+        self.bindings
+            .get(&action)
+            .map(|bindings| bindings.as_slice())
+            .unwrap_or(&[])
+    }
+
+    //Replaces the existing key (or button) for an action, bindings of the other kind are kept
+    pub fn rebind(&mut self, action: GameAction, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_insert_with(Vec::new);
+        bindings.retain(|existing| {
+            !matches!(
+                (existing, binding),
+                (Binding::Key(_), Binding::Key(_)) | (Binding::Button(_), Binding::Button(_))
+            )
+        });
+        bindings.push(binding);
+    }
+
+    //Lets the rebind menu warn when a key or button ends up doing two things
+    pub fn other_actions_bound_to(&self, action: GameAction, binding: Binding) -> Vec<GameAction> {
+        ALL_ACTIONS
+            .iter()
+            .filter(|other| **other != action && self.bindings(**other).contains(&binding))
+            .copied()
+            .collect()
+    }
+
+    //Missing actions keep their default bindings, bad lines are skipped
+    pub fn load(path: &Path) -> InputMap {
+        let mut map = InputMap::default();
+        let input = match File::open(path) {
+            Ok(input) => input,
+            Err(_) => return map,
+        };
+        for line in BufReader::new(input).lines().flatten() {
+            if line.starts_with('/') || line.trim().is_empty() {
+                continue;
+            }
+            match parse_binding_line(&line) {
+                Some((action, binding)) => map.rebind(action, binding),
+                None => println!("Bad controls formatting: {}", line),
+            }
+        }
+        map
+    }
+
+    pub fn save(&self, path: &Path) {
+        let mut output = match File::create(path) {
+            Ok(output) => output,
+            Err(err) => {
+                println!("Failed to save controls: {}", err);
+                return;
+            }
+        };
+        let mut contents = String::from("//action Key|Button name\n");
+        for action in ALL_ACTIONS {
+            for binding in self.bindings(action) {
+                contents += &format!("{:?} {}\n", action, binding_name(binding));
+            }
+        }
+        if let Err(err) = output.write_all(contents.as_bytes()) {
+            println!("Failed to save controls: {}", err);
+        }
+    }
+}
+
+pub fn is_bindable(binding: Binding) -> bool {
+    match binding {
+        Binding::Key(key) => BINDABLE_KEYS.contains(&key),
+        Binding::Button(button) => BINDABLE_BUTTONS.contains(&button),
+    }
+}
+
+pub fn binding_name(binding: &Binding) -> String {
+    match binding {
+        Binding::Key(key) => format!("Key {:?}", key),
+        Binding::Button(button) => format!("Button {:?}", button),
+    }
+}
+
+fn parse_binding_line(line: &str) -> Option<(GameAction, Binding)> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    if words.len() != 3 {
+        return None;
+    }
+    let action = *ALL_ACTIONS
+        .iter()
+        .find(|action| format!("{:?}", action) == words[0])?;
+    let binding = match words[1] {
+        "Key" => Binding::Key(
+            *BINDABLE_KEYS
+                .iter()
+                .find(|key| format!("{:?}", key) == words[2])?,
+        ),
+        "Button" => Binding::Button(
+            *BINDABLE_BUTTONS
+                .iter()
+                .find(|button| format!("{:?}", button) == words[2])?,
+        ),
+        _ => return None,
+    };
+    Some((action, binding))
+}
+
+//Gamepads currently plugged in, kept up to date from connection events
+#[derive(Default)]
+pub struct ConnectedGamepads(pub Vec<Gamepad>);
+
+//Gameplay systems check this instead of each owner poking the player
+//Locked while anything holds it, holders are named so they can't release each other
+#[derive(Default)]
+pub struct InputLock {
+    holders: HashSet<&'static str>,
+}
+
+impl InputLock {
+    pub fn set(&mut self, holder: &'static str, locked: bool) {
+        if locked {
+            self.holders.insert(holder);
+        } else {
+            self.holders.remove(holder);
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        !self.holders.is_empty()
+    }
+}
+
+pub struct InputManagerPlugin;
+
+impl Plugin for InputManagerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InputMap::load(Path::new(CONTROLS_PATH)))
+            .init_resource::<Input<GameAction>>()
+            .init_resource::<ConnectedGamepads>()
+            .init_resource::<InputLock>()
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                gamepad_connections
+                    .label("gamepad_connections")
+                    .after(InputSystem),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                update_actions.after("gamepad_connections"),
+            );
+    }
+}
+
+//Hack : https://github.com/bevyengine/bevy/issues/1700#issuecomment-803356041
+// https://bevy-cheatbook.github.io/programming/states.html#with-input
+pub fn reset_input(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut actions: ResMut<Input<GameAction>>,
+) {
+    keyboard_input.clear();
+    actions.clear();
+}
+
+fn gamepad_connections(
+    mut gamepads: ResMut<ConnectedGamepads>,
+    mut gamepad_events: EventReader<GamepadEvent>,
+) {
+    for GamepadEvent(gamepad, event_type) in gamepad_events.iter() {
+        match event_type {
+            GamepadEventType::Connected => {
+                if !gamepads.0.contains(gamepad) {
+                    gamepads.0.push(*gamepad);
+                }
+            }
+            GamepadEventType::Disconnected => {
+                gamepads.0.retain(|connected| connected != gamepad);
+            }
+            _ => {}
+        }
+    }
+}
+
+//Some controllers report the dpad as an axis instead of buttons so check both
+//Sticks always move, they aren't part of the rebindable map
+fn axis_pressed(action: GameAction, gamepad: Gamepad, axes: &Axis<GamepadAxis>) -> bool {
+    let value = |axis_type| axes.get(GamepadAxis(gamepad, axis_type)).unwrap_or(0.0);
+    let (stick, dpad) = match action {
+        GameAction::MoveUp | GameAction::MoveDown => (
+            value(GamepadAxisType::LeftStickY),
+            value(GamepadAxisType::DPadY),
+        ),
+        GameAction::MoveLeft | GameAction::MoveRight => (
+            value(GamepadAxisType::LeftStickX),
+            value(GamepadAxisType::DPadX),
+        ),
+        _ => return false,
+    };
+    match action {
+        GameAction::MoveUp | GameAction::MoveRight => {
+            stick > STICK_DEADZONE || dpad > STICK_DEADZONE
+        }
+        _ => stick < -STICK_DEADZONE || dpad < -STICK_DEADZONE,
+    }
+}
+
+fn update_actions(
+    mut actions: ResMut<Input<GameAction>>,
+    input_map: Res<InputMap>,
+    keyboard: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<ConnectedGamepads>,
+) {
+    actions.clear();
+    for action in ALL_ACTIONS {
+        let binding_held = input_map
+            .bindings(action)
+            .iter()
+            .any(|binding| match binding {
+                Binding::Key(key) => keyboard.pressed(*key),
+                Binding::Button(button) => gamepads
+                    .0
+                    .iter()
+                    .any(|gamepad| buttons.pressed(GamepadButton(*gamepad, *button))),
+            });
+        let axis_held = gamepads
+            .0
+            .iter()
+            .any(|gamepad| axis_pressed(action, *gamepad, &axes));
+
+        if binding_held || axis_held {
+            actions.press(action);
+        } else if actions.pressed(action) {
+            actions.release(action);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    fn same_bindings(a: &InputMap, b: &InputMap) -> bool {
+        ALL_ACTIONS
+            .iter()
+            .all(|action| a.bindings(*action) == b.bindings(*action))
+    }
+
+    #[test]
+    fn parses_binding_lines() {
+        assert_eq!(
+            parse_binding_line("Confirm Key Space"),
+            Some((GameAction::Confirm, Binding::Key(KeyCode::Space)))
+        );
+        assert_eq!(
+            parse_binding_line("  MoveUp   Button  DPadUp "),
+            Some((
+                GameAction::MoveUp,
+                Binding::Button(GamepadButtonType::DPadUp)
+            ))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_binding_lines() {
+        assert_eq!(parse_binding_line("Confirm Key"), None);
+        assert_eq!(parse_binding_line("Confirm Key Space Extra"), None);
+        assert_eq!(parse_binding_line("Jump Key Space"), None);
+        assert_eq!(parse_binding_line("Confirm Mouse Left"), None);
+        assert_eq!(parse_binding_line("Confirm Key NotAKey"), None);
+        assert_eq!(parse_binding_line("confirm Key Space"), None);
+    }
+
+    #[test]
+    fn rebind_only_replaces_the_same_kind() {
+        let mut map = InputMap::default();
+        map.rebind(GameAction::Confirm, Binding::Key(KeyCode::Z));
+        let bindings = map.bindings(GameAction::Confirm);
+        assert_eq!(bindings.len(), 2);
+        assert!(bindings.contains(&Binding::Key(KeyCode::Z)));
+        assert!(bindings
+            .iter()
+            .any(|binding| matches!(binding, Binding::Button(_))));
+    }
+
+    #[test]
+    fn save_then_load_round_trips() {
+        let path = temp_path("controls_round_trip.txt");
+        let mut map = InputMap::default();
+        map.rebind(GameAction::Menu, Binding::Key(KeyCode::M));
+        map.rebind(
+            GameAction::Cancel,
+            Binding::Button(GamepadButtonType::North),
+        );
+        map.save(&path);
+        let loaded = InputMap::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(same_bindings(&map, &loaded));
+    }
+
+    #[test]
+    fn missing_files_load_defaults() {
+        let path = temp_path("controls_missing.txt");
+        assert!(same_bindings(&InputMap::load(&path), &InputMap::default()));
+    }
+
+    #[test]
+    fn bad_lines_are_skipped() {
+        let path = temp_path("controls_broken.txt");
+        std::fs::write(&path, "//comment\nConfirm Key Z\nnonsense\nMenu Key F5\n").unwrap();
+        let loaded = InputMap::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut expected = InputMap::default();
+        expected.rebind(GameAction::Confirm, Binding::Key(KeyCode::Z));
+        assert!(same_bindings(&loaded, &expected));
+    }
+
+    #[test]
+    fn only_bindable_keys_are_offered() {
+        assert!(is_bindable(Binding::Key(KeyCode::Escape)));
+        assert!(!is_bindable(Binding::Key(KeyCode::F5)));
+        assert!(!is_bindable(Binding::Button(GamepadButtonType::Other(40))));
+        for key in BINDABLE_KEYS {
+            let line = format!("Confirm {}", binding_name(&Binding::Key(key)));
+            assert_eq!(
+                parse_binding_line(&line),
+                Some((GameAction::Confirm, Binding::Key(key)))
+            );
+        }
+    }
+
+    #[test]
+    fn finds_other_actions_on_the_same_key() {
+        let map = InputMap::default();
+        assert_eq!(
+            map.other_actions_bound_to(GameAction::Cancel, Binding::Key(KeyCode::Escape)),
+            vec![GameAction::Menu]
+        );
+        assert!(map
+            .other_actions_bound_to(GameAction::Confirm, Binding::Key(KeyCode::Return))
+            .is_empty());
+    }
+}
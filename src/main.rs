@@ -4,27 +4,60 @@
 use audio::AudioManagerPlugin;
 #[allow(unused_imports)]
 use bevy::prelude::*;
-use bevy::render::camera::ScalingMode;
-use bevy::window::WindowMode;
 
+mod animation;
 mod ascii;
 mod audio;
+mod camera;
 mod combat;
+mod combat_animation;
+mod combat_hud;
+mod combat_log;
 mod debug;
 mod enemy;
+mod font;
+mod frame_pacing;
 mod graphics;
+mod input;
+mod music;
 mod nine_sprite;
+mod npc;
+mod options_menu;
+mod pathfinding;
 mod player;
+mod rebind_menu;
 mod screen_fadeout;
+mod settings;
+#[cfg(test)]
+mod test_util;
 mod tilemap;
+mod tween;
+mod ui;
+mod wild;
 
+use animation::SpriteAnimationPlugin;
 use ascii::{spawn_ascii_sprite, AsciiPlugin, AsciiSheet};
+use camera::CameraPlugin;
 use combat::CombatPlugin;
+use combat_animation::CombatAnimationPlugin;
+use combat_hud::CombatHudPlugin;
+use combat_log::CombatLogPlugin;
 use debug::DebugPlugin;
+use frame_pacing::FramePacingPlugin;
 use graphics::GraphicsPlugin;
-use nine_sprite::NineSpritePlugin;
+use input::InputManagerPlugin;
+use music::MusicPlugin;
+use npc::NpcPlugin;
+use options_menu::OptionsMenuPlugin;
 use player::PlayerPlugin;
+use rebind_menu::RebindMenuPlugin;
+use screen_fadeout::ScreenTransitionPlugin;
+use settings::{Settings, SettingsPlugin, SETTINGS_PATH};
+use std::path::Path;
 use tilemap::TileMapPlugin;
+use tween::TweenPlugin;
+use ui::UiPlugin;
+use wild::WildMonsterPlugin;
 
 pub const RESOLUTION: f32 = 16.0 / 9.0;
 pub const TILE_SIZE: f32 = 0.10;
@@ -35,37 +68,46 @@ enum GameState {
     //Menu,
     Overworld,
     Combat,
+    Options,
+    Rebind,
 }
 
 fn main() {
-    let height = 900.0;
+    //Window is made from the settings so they have to be loaded first
+    let settings = Settings::load(Path::new(SETTINGS_PATH));
 
     App::new()
         .insert_resource(ClearColor(CLEAR))
         .insert_resource(Msaa { samples: 1 })
-        .insert_resource(WindowDescriptor {
-            width: height * RESOLUTION,
-            height: height,
-            title: "Monster Fighter".to_string(),
-            vsync: true,
-            resizable: false,
-            mode: WindowMode::Windowed,
-            ..Default::default()
-        })
+        .insert_resource(settings.window_descriptor())
+        .insert_resource(settings)
         .add_plugins(DefaultPlugins)
-        //.add_system(play_single_sound)
         .add_state(GameState::Overworld)
+        //Adds the fixed step stage so has to come before anything using it
+        .add_plugin(FramePacingPlugin)
         .add_plugin(AudioManagerPlugin)
+        .add_plugin(MusicPlugin)
+        .add_plugin(InputManagerPlugin)
         .add_plugin(DebugPlugin)
         .add_plugin(TileMapPlugin)
+        .add_plugin(NpcPlugin)
+        .add_plugin(WildMonsterPlugin)
         .add_plugin(PlayerPlugin)
+        .add_plugin(RebindMenuPlugin)
+        .add_plugin(OptionsMenuPlugin)
+        .add_plugin(SettingsPlugin)
         .add_plugin(CombatPlugin)
-        .add_plugin(NineSpritePlugin)
+        .add_plugin(CombatAnimationPlugin)
+        .add_plugin(CombatHudPlugin)
+        .add_plugin(CombatLogPlugin)
+        .add_plugin(UiPlugin)
         .add_plugin(AsciiPlugin)
         .add_plugin(GraphicsPlugin)
-        .add_startup_system(spawn_camera)
+        .add_plugin(SpriteAnimationPlugin)
+        .add_plugin(TweenPlugin)
+        .add_plugin(ScreenTransitionPlugin)
+        .add_plugin(CameraPlugin)
         //.add_startup_system(spawn_dummy_sprite)
-        .add_system(frame_limiter)
         .run();
 }
 
@@ -80,20 +122,3 @@ fn spawn_dummy_sprite(mut commands: Commands, ascii: Res<AsciiSheet>) {
         Vec3::splat(1.0),
     );
 }
-
-// Janky but keeps my laptop from hitting 400fps and using 100% cpu
-// https://github.com/bevyengine/bevy/issues/1343
-fn frame_limiter() {
-    use std::{thread, time};
-    thread::sleep(time::Duration::from_millis(10));
-}
-
-fn spawn_camera(mut commands: Commands) {
-    let mut camera = OrthographicCameraBundle::new_2d();
-    camera.orthographic_projection.scaling_mode = ScalingMode::None;
-    camera.orthographic_projection.top = 1.0;
-    camera.orthographic_projection.bottom = -1.0;
-    camera.orthographic_projection.right = 1.0 * RESOLUTION;
-    camera.orthographic_projection.left = -1.0 * RESOLUTION;
-    commands.spawn_bundle(camera);
-}
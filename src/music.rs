@@ -0,0 +1,177 @@
+use bevy::prelude::*;
+use bevy_kira_audio::{Audio, AudioChannel, InstanceHandle, PlaybackState};
+
+use crate::audio::{AudioState, SoundChannel};
+use crate::tilemap::MapMetadata;
+use crate::GameState;
+
+const BATTLE_THEME: &str = "music/battle.wav";
+pub const VICTORY_JINGLE: &str = "music/victory.wav";
+//Seconds for a track to fade all the way in or out
+const FADE_TIME: f32 = 1.0;
+
+pub struct MusicPlugin;
+
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Music>()
+            .add_system(update_music)
+            .add_system_set(SystemSet::on_update(GameState::Overworld).with_system(play_map_music))
+            .add_system_set(SystemSet::on_enter(GameState::Combat).with_system(start_battle_music))
+            .add_system_set(SystemSet::on_exit(GameState::Combat).with_system(end_battle_music));
+    }
+}
+
+struct MusicTrack {
+    //Into Music::channels
+    channel: usize,
+    instance: InstanceHandle,
+    looped: bool,
+    volume: f32,
+    target: f32,
+    //Paused instead of stopped once faded out so it can pick up where it left off
+    held: bool,
+}
+
+#[derive(Default)]
+pub struct Music {
+    tracks: Vec<MusicTrack>,
+    //The current map's track, stays the same through battles
+    map_track: Option<String>,
+    //Every track fades on its own channel, reused once its track is gone
+    channels: Vec<AudioChannel>,
+}
+
+impl Music {
+    //A held track, one fading out and a new one can all be around at once (the victory
+    //jingle starting over the battle theme) so another channel is made rather than
+    //cutting off something that can still be heard
+    fn free_channel(&mut self) -> usize {
+        let tracks = &self.tracks;
+        let busy = |channel: usize| tracks.iter().any(|track| track.channel == channel);
+        if let Some(channel) = (0..self.channels.len()).find(|i| !busy(*i)) {
+            return channel;
+        }
+        let name = format!("music_{}", self.channels.len());
+        self.channels.push(AudioChannel::new(name));
+        self.channels.len() - 1
+    }
+
+    //Fades out whatever is playing and fades the new track in over it
+    pub fn play(&mut self, audio: &Audio, assets: &AssetServer, path: &str, looped: bool) {
+        self.fade_out();
+        let index = self.free_channel();
+        let channel = &self.channels[index];
+
+        //Audio waits for the asset to load before starting so this can be called right away
+        let handle = assets.load(path);
+        audio.set_volume_in_channel(0.0, channel);
+        let instance = if looped {
+            audio.play_looped_in_channel(handle, channel)
+        } else {
+            audio.play_in_channel(handle, channel)
+        };
+        self.tracks.push(MusicTrack {
+            channel: index,
+            instance: instance,
+            looped: looped,
+            volume: 0.0,
+            target: 1.0,
+            held: false,
+        });
+    }
+
+    //Held tracks are left alone
+    pub fn fade_out(&mut self) {
+        for track in self.tracks.iter_mut().filter(|track| !track.held) {
+            track.target = 0.0;
+        }
+    }
+
+    //Fades out what's playing and pauses it until resume_held
+    //Tracks already on their way out are left to stop
+    fn hold(&mut self) {
+        for track in self.tracks.iter_mut().filter(|track| track.target > 0.0) {
+            track.held = true;
+            track.target = 0.0;
+        }
+    }
+
+    //Anything that isn't held fades out so the held tracks come back alone
+    fn resume_held(&mut self, audio: &Audio) {
+        for track in self.tracks.iter_mut() {
+            if track.held {
+                audio.resume_channel(&self.channels[track.channel]);
+                track.held = false;
+                track.target = 1.0;
+            } else {
+                track.target = 0.0;
+            }
+        }
+    }
+}
+
+//Swaps tracks when a map with different music is loaded
+fn play_map_music(
+    mut music: ResMut<Music>,
+    metadata: Res<MapMetadata>,
+    audio: Res<Audio>,
+    assets: Res<AssetServer>,
+) {
+    if music.map_track == metadata.music {
+        return;
+    }
+    music.map_track = metadata.music.clone();
+    match &metadata.music {
+        Some(path) => music.play(&audio, &assets, path, true),
+        None => music.fade_out(),
+    }
+}
+
+fn start_battle_music(mut music: ResMut<Music>, audio: Res<Audio>, assets: Res<AssetServer>) {
+    music.hold();
+    music.play(&audio, &assets, BATTLE_THEME, true);
+}
+
+fn end_battle_music(mut music: ResMut<Music>, audio: Res<Audio>) {
+    music.resume_held(&audio);
+}
+
+fn update_music(
+    mut music: ResMut<Music>,
+    audio: Res<Audio>,
+    audio_state: Res<AudioState>,
+    time: Res<Time>,
+) {
+    let step = time.delta_seconds() / FADE_TIME;
+    let music = &mut *music;
+    for track in music.tracks.iter_mut() {
+        let channel = &music.channels[track.channel];
+        if track.volume != track.target {
+            track.volume = if track.volume < track.target {
+                (track.volume + step).min(track.target)
+            } else {
+                (track.volume - step).max(track.target)
+            };
+            if track.volume == 0.0 {
+                if track.held {
+                    audio.pause_channel(channel);
+                } else {
+                    audio.stop_channel(channel);
+                }
+            }
+        } else if !audio_state.is_changed() {
+            continue;
+        }
+        //Channels don't follow the main volume so it's applied here
+        let volume = track.volume * audio_state.channel_volume(SoundChannel::Music);
+        audio.set_volume_in_channel(volume, channel);
+    }
+
+    //Drop tracks that faded out or were played once and reached the end
+    music.tracks.retain(|track| {
+        let finished =
+            !track.looped && audio.state(track.instance.clone()) == PlaybackState::Stopped;
+        track.held || ((track.target > 0.0 || track.volume > 0.0) && !finished)
+    });
+}
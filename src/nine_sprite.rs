@@ -2,16 +2,10 @@ use crate::ascii::spawn_ascii_sprite;
 use crate::{AsciiSheet, TILE_SIZE};
 use bevy::prelude::*;
 
-pub struct NineSpritePlugin;
-
-impl Plugin for NineSpritePlugin {
-    fn build(&self, app: &mut App) {
-        app.add_startup_system_to_stage(StartupStage::PreStartup, setup_nine_sprite)
-        //        .add_startup_system(spawn_simple_nine_sprite);
-            ;
-    }
-}
+//Full block on the ascii sheet, used for fills and shadows
+const FILL_INDEX: usize = 13 * 16 + 11;
 
+//Indices on ascii sheet for each piece of the border
 #[derive(Copy, Clone)]
 pub struct NineSpriteIndices {
     upper_left_index: usize,
@@ -22,122 +16,180 @@ pub struct NineSpriteIndices {
     vertical_index: usize,
 }
 
-fn setup_nine_sprite(mut commands: Commands) {
-    //Indices on ascii sheet
-    commands.insert_resource(NineSpriteIndices {
+impl NineSpriteIndices {
+    pub const SINGLE: NineSpriteIndices = NineSpriteIndices {
         upper_left_index: 13 * 16 + 10,
         upper_right_index: 11 * 16 + 15,
         lower_left_index: 12 * 16,
         lower_right_index: 13 * 16 + 9,
         horizontal_index: 12 * 16 + 4,
         vertical_index: 11 * 16 + 3,
-    });
+    };
+    pub const DOUBLE: NineSpriteIndices = NineSpriteIndices {
+        upper_left_index: 12 * 16 + 9,
+        upper_right_index: 11 * 16 + 11,
+        lower_left_index: 12 * 16 + 8,
+        lower_right_index: 11 * 16 + 12,
+        horizontal_index: 12 * 16 + 13,
+        vertical_index: 11 * 16 + 10,
+    };
+}
+
+#[derive(Copy, Clone)]
+pub struct NineSpriteTheme {
+    pub indices: NineSpriteIndices,
+    pub border_color: Color,
+    //Drawn behind the box's contents so the world doesn't show through
+    pub fill_color: Option<Color>,
+    //Peeks out half a tile down and right of the box
+    pub shadow_color: Option<Color>,
 }
 
-#[allow(dead_code)]
-fn spawn_simple_nine_sprite(
-    mut commands: Commands,
-    ascii: Res<AsciiSheet>,
-    indices: Res<NineSpriteIndices>,
-) {
-    spawn_nine_sprite(
-        &mut commands,
-        ascii.clone(),
-        *indices,
-        11.0 * TILE_SIZE,
-        8.0 * TILE_SIZE,
-    );
+impl Default for NineSpriteTheme {
+    fn default() -> Self {
+        NineSpriteTheme {
+            indices: NineSpriteIndices::SINGLE,
+            border_color: Color::rgb(0.3, 0.3, 0.9),
+            fill_color: Some(Color::rgb(0.05, 0.05, 0.15)),
+            shadow_color: None,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct NineSprite {
+    //What the border goes back to after being highlighted
+    pub border_color: Color,
 }
 
+//Any of the sprites making up the edge of a NineSprite, fills and shadows don't have this
 #[derive(Component)]
-pub struct NineSprite;
+pub struct NineSpriteBorder;
+
+fn spawn_piece(
+    commands: &mut Commands,
+    ascii: &AsciiSheet,
+    index: usize,
+    color: Color,
+    center: Vec3,
+    size: Vec2,
+) -> Entity {
+    spawn_ascii_sprite(
+        commands,
+        ascii,
+        index,
+        color,
+        center,
+        (size / TILE_SIZE).extend(1.0),
+    )
+}
+
+//Splits the part of an edge between its corners into whole tiles, the last piece is cut
+//short to fit so the box is exactly the size asked for
+//Returns the center and length of each piece, starting from the low end
+fn tile_edge(start: f32, length: f32) -> Vec<(f32, f32)> {
+    let mut pieces = Vec::new();
+    let mut covered = 0.0;
+    //Small slop so float error doesn't add a sliver piece
+    while length - covered > 0.01 {
+        let piece = TILE_SIZE.min(length - covered);
+        pieces.push((start + covered + piece / 2.0, piece));
+        covered += piece;
+    }
+    pieces
+}
 
+//Edge glyphs are lines so cutting the last one short looks the same as clipping it
+//Boxes smaller than two tiles shrink their corners to fit
 pub fn spawn_nine_sprite(
     commands: &mut Commands,
     ascii: AsciiSheet,
-    indices: NineSpriteIndices,
+    theme: NineSpriteTheme,
     width: f32,
     height: f32,
 ) -> Entity {
-    assert!(width >= 2.0 * TILE_SIZE);
-    assert!(height >= 2.0 * TILE_SIZE);
+    let indices = theme.indices;
+    let color = theme.border_color;
+    let corner = Vec2::new(TILE_SIZE.min(width / 2.0), TILE_SIZE.min(height / 2.0));
 
-    let color = Color::rgb(0.3, 0.3, 0.9);
-    let mut sprites = Vec::new();
+    let left = -width / 2.0 + corner.x / 2.0;
+    let right = width / 2.0 - corner.x / 2.0;
+    let up = height / 2.0 - corner.y / 2.0;
+    let down = -height / 2.0 + corner.y / 2.0;
 
-    let left = -width / 2.0 + 0.5 * TILE_SIZE;
-    let right = width / 2.0 - 0.5 * TILE_SIZE;
-    let up = height / 2.0 - 0.5 * TILE_SIZE;
-    let down = -height / 2.0 + 0.5 * TILE_SIZE;
+    let mut border = Vec::new();
+    for (index, x, y) in [
+        (indices.upper_left_index, left, up),
+        (indices.upper_right_index, right, up),
+        (indices.lower_left_index, left, down),
+        (indices.lower_right_index, right, down),
+    ] {
+        border.push(spawn_piece(
+            commands,
+            &ascii,
+            index,
+            color,
+            Vec3::new(x, y, 0.0),
+            corner,
+        ));
+    }
+    for (x, length) in tile_edge(-width / 2.0 + corner.x, width - 2.0 * corner.x) {
+        for y in [up, down] {
+            border.push(spawn_piece(
+                commands,
+                &ascii,
+                indices.horizontal_index,
+                color,
+                Vec3::new(x, y, 0.0),
+                Vec2::new(length, corner.y),
+            ));
+        }
+    }
+    for (y, length) in tile_edge(-height / 2.0 + corner.y, height - 2.0 * corner.y) {
+        for x in [left, right] {
+            border.push(spawn_piece(
+                commands,
+                &ascii,
+                indices.vertical_index,
+                color,
+                Vec3::new(x, y, 0.0),
+                Vec2::new(corner.x, length),
+            ));
+        }
+    }
+    for piece in border.iter() {
+        commands.entity(*piece).insert(NineSpriteBorder);
+    }
 
-    sprites.push(spawn_ascii_sprite(
-        commands,
-        &ascii,
-        indices.upper_left_index,
-        color,
-        Vec3::new(left, up, 0.0),
-        Vec3::splat(1.0),
-    ));
-    sprites.push(spawn_ascii_sprite(
-        commands,
-        &ascii,
-        indices.vertical_index,
-        color,
-        Vec3::new(left, 0.0, 0.0),
-        Vec3::new(1.0, height / TILE_SIZE - 2.0, 1.0),
-    ));
-    sprites.push(spawn_ascii_sprite(
-        commands,
-        &ascii,
-        indices.lower_left_index,
-        color,
-        Vec3::new(left, down, 0.0),
-        Vec3::splat(1.0),
-    ));
-    sprites.push(spawn_ascii_sprite(
-        commands,
-        &ascii,
-        indices.horizontal_index,
-        color,
-        Vec3::new(0.0, down, 0.0),
-        Vec3::new(width / TILE_SIZE - 2.0, 1.0, 1.0),
-    ));
-    sprites.push(spawn_ascii_sprite(
-        commands,
-        &ascii,
-        indices.horizontal_index,
-        color,
-        Vec3::new(0.0, up, 0.0),
-        Vec3::new(width / TILE_SIZE - 2.0, 1.0, 1.0),
-    ));
-    sprites.push(spawn_ascii_sprite(
-        commands,
-        &ascii,
-        indices.upper_right_index,
-        color,
-        Vec3::new(right, up, 0.0),
-        Vec3::splat(1.0),
-    ));
-    sprites.push(spawn_ascii_sprite(
-        commands,
-        &ascii,
-        indices.vertical_index,
-        color,
-        Vec3::new(right, 0.0, 0.0),
-        Vec3::new(1.0, height / TILE_SIZE - 2.0, 1.0),
-    ));
-    sprites.push(spawn_ascii_sprite(
-        commands,
-        &ascii,
-        indices.lower_right_index,
-        color,
-        Vec3::new(right, down, 0.0),
-        Vec3::splat(1.0),
-    ));
+    let mut sprites = border;
+    //Fill reaches the middle of the border glyphs so nothing shows between the lines
+    //Slightly behind so the border and anything else in the box draws over it
+    if let Some(fill_color) = theme.fill_color {
+        sprites.push(spawn_piece(
+            commands,
+            &ascii,
+            FILL_INDEX,
+            fill_color,
+            Vec3::new(0.0, 0.0, -0.01),
+            Vec2::new(width, height) - corner,
+        ));
+    }
+    if let Some(shadow_color) = theme.shadow_color {
+        sprites.push(spawn_piece(
+            commands,
+            &ascii,
+            FILL_INDEX,
+            shadow_color,
+            Vec3::new(TILE_SIZE / 2.0, -TILE_SIZE / 2.0, -0.02),
+            Vec2::new(width, height) - corner,
+        ));
+    }
 
     commands
         .spawn()
-        .insert(NineSprite)
+        .insert(NineSprite {
+            border_color: color,
+        })
         .insert(Name::new("NineSpriteBox"))
         //Needs transforms for parent heirarchy system to work
         .insert(Transform::default())
@@ -145,3 +197,25 @@ pub fn spawn_nine_sprite(
         .push_children(&sprites)
         .id()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edges_cover_the_exact_length() {
+        let pieces = tile_edge(-1.0, 2.5 * TILE_SIZE);
+        assert_eq!(pieces.len(), 3);
+        assert_eq!(pieces[0], (-1.0 + TILE_SIZE / 2.0, TILE_SIZE));
+        assert_eq!(pieces[1].1, TILE_SIZE);
+        let (center, length) = pieces[2];
+        assert!((length - TILE_SIZE / 2.0).abs() < 0.0001);
+        assert!((center + length / 2.0 - (-1.0 + 2.5 * TILE_SIZE)).abs() < 0.0001);
+    }
+
+    #[test]
+    fn whole_tiles_have_no_sliver() {
+        assert_eq!(tile_edge(0.0, 3.0 * TILE_SIZE).len(), 3);
+        assert!(tile_edge(0.0, 0.0).is_empty());
+    }
+}
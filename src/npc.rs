@@ -0,0 +1,294 @@
+use bevy::prelude::*;
+use bevy::sprite::collide_aabb::collide;
+use bevy::utils::HashSet;
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng};
+use std::collections::VecDeque;
+
+use crate::graphics::{FacingDirection, GraphicsHandles, Walking};
+use crate::pathfinding::{find_path, neighbours};
+use crate::player::Player;
+use crate::tilemap::{grid_to_world, world_to_grid, MapGrid, TileCollider};
+use crate::{GameState, TILE_SIZE};
+
+#[derive(Clone)]
+pub enum NpcBehavior {
+    //Walks to random tiles within radius of home
+    Wander { home: IVec2, radius: i32 },
+    //Loops through the points in order
+    Patrol { points: Vec<IVec2>, next: usize },
+    //Steps to random neighbouring grass tiles, used by wild monsters
+    Roam,
+}
+
+//Parsed from a map comment, spawned once the map is built
+pub struct NpcSpawn {
+    character: usize,
+    behavior: NpcBehavior,
+}
+
+#[derive(Component)]
+pub struct Npc {
+    behavior: NpcBehavior,
+    path: VecDeque<IVec2>,
+    //Tiles per second
+    speed: f32,
+    //Pause between walks so it doesn't look robotic
+    wait: Timer,
+    step_from: Vec3,
+    step_to: Vec3,
+    step_timer: Timer,
+    moving: bool,
+}
+
+impl Npc {
+    pub fn new(behavior: NpcBehavior, speed: f32) -> Npc {
+        Npc {
+            behavior: behavior,
+            path: VecDeque::new(),
+            speed: speed,
+            wait: Timer::from_seconds(1.0, false),
+            step_from: Vec3::ZERO,
+            step_to: Vec3::ZERO,
+            step_timer: Timer::default(),
+            moving: false,
+        }
+    }
+}
+
+pub struct NpcPlugin;
+
+impl Plugin for NpcPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::Overworld)
+                .with_system(plan_npc_paths.before("npc_movement"))
+                .with_system(npc_movement.label("npc_movement")),
+        );
+    }
+}
+
+//Map comment format:
+// /npc <character> wander <x> <y> <radius>
+// /npc <character> patrol <x1> <y1> <x2> <y2> ...
+pub fn parse_npc(words: &[&str]) -> NpcSpawn {
+    let character = words[0]
+        .parse::<usize>()
+        .expect("Bad npc formatting, no character");
+    let numbers: Vec<i32> = words[2..]
+        .iter()
+        .map(|word| {
+            word.parse::<i32>()
+                .expect("Bad npc formatting, expected a number")
+        })
+        .collect();
+
+    let behavior = match words[1] {
+        "wander" => {
+            assert!(
+                numbers.len() == 3,
+                "Bad npc formatting, wander needs x y radius"
+            );
+            NpcBehavior::Wander {
+                home: IVec2::new(numbers[0], numbers[1]),
+                radius: numbers[2],
+            }
+        }
+        "patrol" => {
+            assert!(
+                !numbers.is_empty() && numbers.len() % 2 == 0,
+                "Bad npc formatting, patrol needs x y pairs"
+            );
+            NpcBehavior::Patrol {
+                points: numbers
+                    .chunks(2)
+                    .map(|point| IVec2::new(point[0], point[1]))
+                    .collect(),
+                next: 0,
+            }
+        }
+        behavior => panic!("Bad npc formatting, unknown behavior {}", behavior),
+    };
+    NpcSpawn {
+        character: character,
+        behavior: behavior,
+    }
+}
+
+pub fn spawn_npc(commands: &mut Commands, graphics: &GraphicsHandles, spawn: NpcSpawn) -> Entity {
+    let start = match &spawn.behavior {
+        NpcBehavior::Wander { home, .. } => *home,
+        NpcBehavior::Patrol { points, .. } => points[0],
+        NpcBehavior::Roam => unreachable!("Roaming npcs aren't spawned from map files"),
+    };
+    //Frame gets set by the animator
+    let mut sprite = TextureAtlasSprite::new(0);
+    sprite.custom_size = Some(Vec2::splat(TILE_SIZE));
+
+    commands
+        .spawn_bundle(SpriteSheetBundle {
+            sprite: sprite,
+            texture_atlas: graphics.characters.clone(),
+            transform: Transform {
+                //Above the map but under the player
+                translation: grid_to_world(start, 800.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Name::new("Npc"))
+        .insert(Npc::new(spawn.behavior, 3.0))
+        //Lets the player bump into npcs like walls
+        .insert(TileCollider)
+        .insert(FacingDirection::Down)
+        .insert(graphics.character_animator(spawn.character))
+        .insert(Walking::default())
+        .id()
+}
+
+fn plan_npc_paths(
+    mut npc_query: Query<(&mut Npc, &Transform)>,
+    grid: Option<Res<MapGrid>>,
+    time: Res<Time>,
+) {
+    let grid = match grid {
+        Some(grid) => grid,
+        None => return,
+    };
+    let mut rng = thread_rng();
+
+    for (mut npc, transform) in npc_query.iter_mut() {
+        if npc.moving || !npc.path.is_empty() {
+            continue;
+        }
+        npc.wait.tick(time.delta());
+        if !npc.wait.finished() {
+            continue;
+        }
+
+        let start = world_to_grid(transform.translation);
+        let goal = match &mut npc.behavior {
+            NpcBehavior::Wander { home, radius } => {
+                *home
+                    + IVec2::new(
+                        rng.gen_range(-*radius..=*radius),
+                        rng.gen_range(-*radius..=*radius),
+                    )
+            }
+            NpcBehavior::Patrol { points, next } => {
+                let goal = points[*next];
+                *next = (*next + 1) % points.len();
+                goal
+            }
+            NpcBehavior::Roam => {
+                let options: Vec<IVec2> = neighbours(start)
+                    .into_iter()
+                    .filter(|tile| grid.is_wild(*tile) && grid.is_walkable(*tile))
+                    .collect();
+                *options.choose(&mut rng).unwrap_or(&start)
+            }
+        };
+
+        //Unreachable goals are just skipped, try again after the next wait
+        if let Some(path) = find_path(&grid, start, goal) {
+            npc.path = path.into();
+        }
+        let next_wait = rng.gen_range(1.0..3.0);
+        npc.wait = Timer::from_seconds(next_wait, false);
+    }
+}
+
+fn facing(from: IVec2, to: IVec2) -> FacingDirection {
+    //Grid y grows downwards
+    if to.x > from.x {
+        FacingDirection::Right
+    } else if to.x < from.x {
+        FacingDirection::Left
+    } else if to.y > from.y {
+        FacingDirection::Down
+    } else {
+        FacingDirection::Up
+    }
+}
+
+//Tiles npcs are standing on or stepping between, no two npcs share one
+fn reserved_tiles<'a>(npcs: impl Iterator<Item = (&'a Npc, &'a Transform)>) -> HashSet<IVec2> {
+    let mut reserved = HashSet::default();
+    for (npc, transform) in npcs {
+        if npc.moving {
+            reserved.insert(world_to_grid(npc.step_from));
+            reserved.insert(world_to_grid(npc.step_to));
+        } else {
+            reserved.insert(world_to_grid(transform.translation));
+        }
+    }
+    reserved
+}
+
+fn npc_movement(
+    mut npc_query: Query<
+        (
+            &mut Npc,
+            &mut Transform,
+            &mut FacingDirection,
+            Option<&mut Walking>,
+        ),
+        Without<Player>,
+    >,
+    player_query: Query<(&Player, &Transform)>,
+    time: Res<Time>,
+) {
+    let (player, player_transform) = player_query.single();
+    let mut reserved = reserved_tiles(
+        npc_query
+            .iter()
+            .map(|(npc, transform, _, _)| (npc, transform)),
+    );
+
+    for (mut npc, mut transform, mut direction, walking) in npc_query.iter_mut() {
+        //Wild monsters are single glyphs without walk animations
+        if let Some(mut walking) = walking {
+            //Still counts as walking between steps so the walk cycle doesn't restart each tile
+            walking.0 = npc.moving || !npc.path.is_empty();
+        }
+
+        if !npc.moving {
+            let next = match npc.path.front() {
+                Some(next) => *next,
+                None => continue,
+            };
+            let target = grid_to_world(next, transform.translation.z);
+            //Wait for the player to get out of the way, even if they're only partly on the tile
+            //Otherwise the npc walks into them and they're stuck inside its collider
+            let blocked_by_player = collide(
+                target,
+                Vec2::splat(TILE_SIZE),
+                player_transform.translation,
+                player.hitbox(),
+            )
+            .is_some();
+            if blocked_by_player {
+                continue;
+            }
+            //Two npcs can end up waiting on each other so they pick somewhere else instead
+            if reserved.contains(&next) {
+                npc.path.clear();
+                continue;
+            }
+            npc.path.pop_front();
+            reserved.insert(next);
+
+            *direction = facing(world_to_grid(transform.translation), next);
+            npc.step_from = transform.translation;
+            npc.step_to = target;
+            npc.step_timer = Timer::from_seconds(1.0 / npc.speed, false);
+            npc.moving = true;
+        }
+
+        npc.step_timer.tick(time.delta());
+        transform.translation = npc.step_from.lerp(npc.step_to, npc.step_timer.percent());
+        if npc.step_timer.finished() {
+            npc.moving = false;
+        }
+    }
+}
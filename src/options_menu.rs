@@ -0,0 +1,262 @@
+use bevy::prelude::*;
+use std::path::Path;
+
+use crate::ascii::{
+    spawn_ascii_text, spawn_ascii_text_with_layout, AsciiSheet, TextLayout, TextWidget,
+};
+use crate::camera::{Anchor, ScreenAnchor};
+use crate::font::FontId;
+use crate::input::{reset_input, GameAction, InputLock};
+use crate::nine_sprite::{spawn_nine_sprite, NineSpriteIndices, NineSpriteTheme};
+use crate::settings::{Settings, ALL_TEXT_SPEEDS, FRAME_CAPS, MAX_WINDOW_SCALE, SETTINGS_PATH};
+use crate::{GameState, TILE_SIZE};
+
+const MENU_WIDTH: f32 = 32.0;
+const VOLUME_STEP: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OptionRow {
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    Window,
+    WindowScale,
+    TextSpeed,
+    FrameCap,
+    Controls,
+    Back,
+}
+
+const ALL_ROWS: [OptionRow; 9] = [
+    OptionRow::MasterVolume,
+    OptionRow::MusicVolume,
+    OptionRow::SfxVolume,
+    OptionRow::Window,
+    OptionRow::WindowScale,
+    OptionRow::TextSpeed,
+    OptionRow::FrameCap,
+    OptionRow::Controls,
+    OptionRow::Back,
+];
+
+#[derive(Component)]
+struct OptionsMenu {
+    selected: usize,
+    text: Entity,
+}
+
+pub struct OptionsMenuPlugin;
+
+impl Plugin for OptionsMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::Overworld).with_system(open_options_menu),
+        )
+        .add_system_set(
+            SystemSet::on_enter(GameState::Options)
+                .with_system(create_options_menu)
+                .with_system(reset_input),
+        )
+        //Controls screen goes on top, the menu comes back when it closes
+        .add_system_set(SystemSet::on_pause(GameState::Options).with_system(delete_options_menu))
+        .add_system_set(
+            SystemSet::on_resume(GameState::Options)
+                .with_system(create_options_menu)
+                .with_system(reset_input),
+        )
+        .add_system_set(SystemSet::on_update(GameState::Options).with_system(options_menu_input))
+        .add_system_set(
+            SystemSet::on_exit(GameState::Options)
+                .with_system(delete_options_menu)
+                .with_system(reset_input),
+        );
+    }
+}
+
+fn open_options_menu(
+    actions: Res<Input<GameAction>>,
+    input_lock: Res<InputLock>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if !input_lock.is_locked() && actions.just_pressed(GameAction::Menu) {
+        //Push so the overworld is paused underneath instead of torn down
+        state
+            .push(GameState::Options)
+            .expect("Failed to change state");
+    }
+}
+
+fn row_name(row: OptionRow) -> &'static str {
+    match row {
+        OptionRow::MasterVolume => "Master volume",
+        OptionRow::MusicVolume => "Music volume",
+        OptionRow::SfxVolume => "Sound volume",
+        OptionRow::Window => "Window",
+        OptionRow::WindowScale => "Window size",
+        OptionRow::TextSpeed => "Text speed",
+        OptionRow::FrameCap => "Frame cap",
+        OptionRow::Controls => "Controls",
+        OptionRow::Back => "Back",
+    }
+}
+
+fn row_value(row: OptionRow, settings: &Settings) -> String {
+    let value = match row {
+        OptionRow::MasterVolume => format!("{:.0}%", settings.master_volume * 100.0),
+        OptionRow::MusicVolume => format!("{:.0}%", settings.music_volume * 100.0),
+        OptionRow::SfxVolume => format!("{:.0}%", settings.sfx_volume * 100.0),
+        OptionRow::Window if settings.fullscreen => "Fullscreen".to_string(),
+        OptionRow::Window => "Windowed".to_string(),
+        OptionRow::WindowScale => format!("{}x", settings.window_scale),
+        OptionRow::TextSpeed => format!("{:?}", settings.text_speed),
+        OptionRow::FrameCap => match settings.frame_cap {
+            Some(cap) => format!("{} fps", cap),
+            None => "Off".to_string(),
+        },
+        OptionRow::Controls | OptionRow::Back => return String::new(),
+    };
+    format!("< {} >", value)
+}
+
+fn menu_text(selected: usize, settings: &Settings) -> String {
+    let mut lines = Vec::new();
+    for (i, row) in ALL_ROWS.iter().enumerate() {
+        let line = format!("{:<15}{}", row_name(*row), row_value(*row, settings));
+        if i == selected {
+            lines.push(format!("{{red}}> {}{{/}}", line));
+        } else {
+            lines.push(format!("  {}", line));
+        }
+    }
+    lines.join("\n")
+}
+
+fn create_options_menu(mut commands: Commands, ascii: Res<AsciiSheet>, settings: Res<Settings>) {
+    let width = MENU_WIDTH * TILE_SIZE;
+    let height = (ALL_ROWS.len() as f32 + 4.0) * TILE_SIZE;
+    let theme = NineSpriteTheme {
+        indices: NineSpriteIndices::DOUBLE,
+        shadow_color: Some(Color::rgba(0.0, 0.0, 0.0, 0.5)),
+        ..Default::default()
+    };
+    let frame = spawn_nine_sprite(&mut commands, ascii.clone(), theme, width, height);
+    let top = height / 2.0 - 1.5 * TILE_SIZE;
+    let title = spawn_ascii_text_with_layout(
+        &mut commands,
+        "Options",
+        Vec3::new(-width / 2.0 + 3.0 * TILE_SIZE, top, 0.0),
+        TextLayout {
+            font: FontId::Large,
+            ..Default::default()
+        },
+    );
+    let text = spawn_ascii_text(
+        &mut commands,
+        &menu_text(0, &settings),
+        Vec3::new(-width / 2.0 + TILE_SIZE, top - 2.0 * TILE_SIZE, 0.0),
+    );
+
+    commands
+        .spawn()
+        .insert(Name::new("OptionsMenu"))
+        .insert(OptionsMenu {
+            selected: 0,
+            text: text,
+        })
+        .insert(ScreenAnchor {
+            anchor: Anchor::Center,
+            offset: Vec2::ZERO,
+        })
+        //Over the world but under screen fades
+        .insert(Transform::from_xyz(0.0, 0.0, 950.0))
+        .insert(GlobalTransform::default())
+        .push_children(&[frame, title, text]);
+}
+
+//Left and right cycle through the values, Cancel saves and goes back
+fn change_setting(row: OptionRow, step: i32, settings: &mut Settings) {
+    let volume = |volume: f32| (volume + step as f32 * VOLUME_STEP).clamp(0.0, 1.0);
+    match row {
+        OptionRow::MasterVolume => settings.master_volume = volume(settings.master_volume),
+        OptionRow::MusicVolume => settings.music_volume = volume(settings.music_volume),
+        OptionRow::SfxVolume => settings.sfx_volume = volume(settings.sfx_volume),
+        OptionRow::Window => settings.fullscreen = !settings.fullscreen,
+        OptionRow::WindowScale => {
+            let scale = settings.window_scale as i32 + step;
+            settings.window_scale = scale.clamp(1, MAX_WINDOW_SCALE as i32) as u32;
+        }
+        OptionRow::TextSpeed => {
+            let len = ALL_TEXT_SPEEDS.len() as i32;
+            let current = ALL_TEXT_SPEEDS
+                .iter()
+                .position(|speed| *speed == settings.text_speed)
+                .unwrap_or(0) as i32;
+            settings.text_speed = ALL_TEXT_SPEEDS[(current + step).rem_euclid(len) as usize];
+        }
+        OptionRow::FrameCap => {
+            let len = FRAME_CAPS.len() as i32;
+            //Caps set by hand in the file that aren't in the list start from the top
+            let current = FRAME_CAPS
+                .iter()
+                .position(|cap| *cap == settings.frame_cap)
+                .unwrap_or(0) as i32;
+            settings.frame_cap = FRAME_CAPS[(current + step).rem_euclid(len) as usize];
+        }
+        OptionRow::Controls | OptionRow::Back => {}
+    }
+}
+
+fn options_menu_input(
+    mut menu_query: Query<&mut OptionsMenu>,
+    mut text_query: Query<&mut TextWidget>,
+    actions: Res<Input<GameAction>>,
+    mut settings: ResMut<Settings>,
+    mut state: ResMut<State<GameState>>,
+) {
+    //Not there for a frame after coming back from the controls screen
+    let mut menu = match menu_query.get_single_mut() {
+        Ok(menu) => menu,
+        Err(_) => return,
+    };
+    let row = ALL_ROWS[menu.selected];
+    let mut new_settings = settings.clone();
+
+    if actions.just_pressed(GameAction::Cancel)
+        || (row == OptionRow::Back && actions.just_pressed(GameAction::Confirm))
+    {
+        settings.save(Path::new(SETTINGS_PATH));
+        state.pop().expect("Failed to change state");
+        return;
+    } else if row == OptionRow::Controls && actions.just_pressed(GameAction::Confirm) {
+        state
+            .push(GameState::Rebind)
+            .expect("Failed to change state");
+        return;
+    } else if actions.just_pressed(GameAction::MoveDown) {
+        menu.selected = (menu.selected + 1) % ALL_ROWS.len();
+    } else if actions.just_pressed(GameAction::MoveUp) {
+        menu.selected = (menu.selected + ALL_ROWS.len() - 1) % ALL_ROWS.len();
+    } else if actions.just_pressed(GameAction::MoveRight)
+        || actions.just_pressed(GameAction::Confirm)
+    {
+        change_setting(row, 1, &mut new_settings);
+    } else if actions.just_pressed(GameAction::MoveLeft) {
+        change_setting(row, -1, &mut new_settings);
+    }
+
+    //Only touch the resource when something changed so everything else isn't reapplied
+    if new_settings != *settings {
+        *settings = new_settings;
+    }
+    if menu.is_changed() || settings.is_changed() {
+        if let Ok(mut text) = text_query.get_mut(menu.text) {
+            text.text = menu_text(menu.selected, &settings);
+        }
+    }
+}
+
+fn delete_options_menu(mut commands: Commands, menu_query: Query<Entity, With<OptionsMenu>>) {
+    for menu in menu_query.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+}
@@ -0,0 +1,157 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::tilemap::MapGrid;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct OpenTile {
+    //Cost so far plus the distance estimate to the goal
+    estimate: i32,
+    tile: IVec2,
+}
+
+//Reversed so the BinaryHeap pops the cheapest tile first
+impl Ord for OpenTile {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .cmp(&self.estimate)
+            .then_with(|| self.tile.x.cmp(&other.tile.x))
+            .then_with(|| self.tile.y.cmp(&other.tile.y))
+    }
+}
+
+impl PartialOrd for OpenTile {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+pub fn neighbours(tile: IVec2) -> [IVec2; 4] {
+    [
+        tile + IVec2::new(1, 0),
+        tile + IVec2::new(-1, 0),
+        tile + IVec2::new(0, 1),
+        tile + IVec2::new(0, -1),
+    ]
+}
+
+fn manhattan(a: IVec2, b: IVec2) -> i32 {
+    (a.x - b.x).abs() + (a.y - b.y).abs()
+}
+
+//A* over the map grid with 4 way movement
+//Returns the tiles to step through in order, not including start
+pub fn find_path(grid: &MapGrid, start: IVec2, goal: IVec2) -> Option<Vec<IVec2>> {
+    if !grid.is_walkable(goal) {
+        return None;
+    }
+    if start == goal {
+        return Some(Vec::new());
+    }
+
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<IVec2, IVec2> = HashMap::default();
+    let mut cost_so_far: HashMap<IVec2, i32> = HashMap::default();
+    open.push(OpenTile {
+        estimate: manhattan(start, goal),
+        tile: start,
+    });
+    cost_so_far.insert(start, 0);
+
+    while let Some(OpenTile { tile, .. }) = open.pop() {
+        if tile == goal {
+            let mut path = vec![goal];
+            let mut current = goal;
+            while let Some(&previous) = came_from.get(&current) {
+                if previous == start {
+                    break;
+                }
+                path.push(previous);
+                current = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        let cost = cost_so_far[&tile] + 1;
+        for next in neighbours(tile) {
+            if !grid.is_walkable(next) {
+                continue;
+            }
+            if cost_so_far.get(&next).map_or(true, |&old| cost < old) {
+                cost_so_far.insert(next, cost);
+                came_from.insert(next, tile);
+                open.push(OpenTile {
+                    estimate: cost + manhattan(next, goal),
+                    tile: next,
+                });
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_connected(start: IVec2, path: &[IVec2]) -> bool {
+        let mut previous = start;
+        for tile in path {
+            if manhattan(previous, *tile) != 1 {
+                return false;
+            }
+            previous = *tile;
+        }
+        true
+    }
+
+    #[test]
+    fn straight_path_in_open_room() {
+        let grid = MapGrid::from_rows(&["....", "....", "...."]);
+        let path = find_path(&grid, IVec2::new(0, 1), IVec2::new(3, 1)).unwrap();
+        assert_eq!(
+            path,
+            vec![IVec2::new(1, 1), IVec2::new(2, 1), IVec2::new(3, 1)]
+        );
+    }
+
+    #[test]
+    fn path_to_start_is_empty() {
+        let grid = MapGrid::from_rows(&["..", ".."]);
+        assert_eq!(
+            find_path(&grid, IVec2::new(1, 1), IVec2::new(1, 1)),
+            Some(Vec::new())
+        );
+    }
+
+    #[test]
+    fn path_goes_around_walls() {
+        let grid = MapGrid::from_rows(&[".#...", ".#.#.", "...#."]);
+        let start = IVec2::new(0, 0);
+        let goal = IVec2::new(4, 0);
+        let path = find_path(&grid, start, goal).unwrap();
+        //Down the first column, under the first wall, then up and over the top of the second
+        assert_eq!(path.len(), 8);
+        assert_eq!(path.last(), Some(&goal));
+        assert!(is_connected(start, &path));
+        assert!(path.iter().all(|tile| grid.is_walkable(*tile)));
+    }
+
+    #[test]
+    fn no_path_to_walls_or_off_the_map() {
+        let grid = MapGrid::from_rows(&["..#", "..."]);
+        assert_eq!(find_path(&grid, IVec2::ZERO, IVec2::new(2, 0)), None);
+        assert_eq!(find_path(&grid, IVec2::ZERO, IVec2::new(3, 0)), None);
+        assert_eq!(find_path(&grid, IVec2::ZERO, IVec2::new(0, -1)), None);
+    }
+
+    #[test]
+    fn no_path_into_walled_off_area() {
+        let grid = MapGrid::from_rows(&["..#..", "..#..", "..#.."]);
+        assert_eq!(find_path(&grid, IVec2::ZERO, IVec2::new(4, 2)), None);
+    }
+}
@@ -1,26 +1,49 @@
 use bevy::utils::Duration;
 
+use bevy::ecs::schedule::ShouldRun;
 use bevy::prelude::*;
 use bevy::sprite::collide_aabb::collide;
 use bevy_inspector_egui::{Inspectable, RegisterInspectable};
 use rand::{thread_rng, Rng};
 
+use crate::camera::{clamp_to_bounds, CameraFollow, ViewSize};
 use crate::debug::ENABLE_INSPECTOR;
-use crate::graphics::{AnimatedSprite, FacingDirection, GraphicsHandles, PlayerAnimations};
-use crate::screen_fadeout::{create_fadeout, fadeout};
-use crate::tilemap::{Door, ExitEvent, TileCollider, WildSpawn};
+use crate::enemy::{random_enemy_type, Encounter, EnemyType};
+use crate::frame_pacing::{FixedUpdate, FIXED_TIMESTEP};
+use crate::graphics::{FacingDirection, GraphicsHandles, IdleFidget, Walking};
+use crate::input::{reset_input, GameAction, InputLock};
+use crate::screen_fadeout::{create_fadeout, fadeout, TransitionStyles, TRANSITION_LOCK};
+use crate::tilemap::{
+    Door, EncounterMode, ExitEvent, MapBounds, MapMetadata, TileCollider, WildSpawn,
+};
 use crate::{AsciiSheet, GameState, TILE_SIZE};
 
+//Index into characters.png, see GraphicsHandles::character_animator
+const PLAYER_CHARACTER: usize = 1;
+
 #[derive(Clone, Inspectable)]
-pub struct CombatEvent;
+pub struct CombatEvent {
+    pub enemy_type: EnemyType,
+    pub level: i64,
+}
+
+#[derive(Clone, Copy, PartialEq, Inspectable)]
+pub enum MovementMode {
+    Free,
+    //Moves exactly one tile per key press, makes lining up with doors easy
+    TileStep,
+}
 
 #[derive(Component, Inspectable)]
 pub struct Player {
-    pub current_direction: FacingDirection,
     speed: f32,
     hitbox_size: f32,
-    just_moved: bool,
-    pub active: bool,
+    //Fixed steps this frame that moved the player, cleared at the end of the frame
+    moved_steps: u32,
+    //Whether the latest fixed step moved the player
+    //Kept through frames without a fixed step so the walk cycle doesn't flicker
+    moving: bool,
+    pub movement_mode: MovementMode,
 }
 
 #[derive(Component, Default, Reflect)]
@@ -29,6 +52,36 @@ pub struct EncounterTracker {
     timer: Timer,
     min_time: f32,
     max_time: f32,
+    //Odds of an encounter for each grass tile stepped on in tile step mode
+    step_chance: f32,
+}
+
+//Progress of the current step when in MovementMode::TileStep
+#[derive(Component, Default)]
+pub struct TileStep {
+    start: Vec3,
+    target: Vec3,
+    timer: Timer,
+    moving: bool,
+    //Only true on the frame a step finishes, cleared at the end of the frame
+    just_stepped: bool,
+    //Direction pressed mid step, taken as soon as the current step finishes
+    buffered: Option<FacingDirection>,
+}
+
+impl Player {
+    pub fn hitbox(&self) -> Vec2 {
+        Vec2::splat(TILE_SIZE * self.hitbox_size)
+    }
+}
+
+impl TileStep {
+    //Drop any step in progress, needed when the player is teleported
+    pub fn cancel(&mut self) {
+        self.moving = false;
+        self.just_stepped = false;
+        self.buffered = None;
+    }
 }
 
 pub struct PlayerPlugin;
@@ -39,12 +92,25 @@ impl Plugin for PlayerPlugin {
             .add_event::<CombatEvent>()
             .add_system(fadeout::<CombatEvent>)
             .add_system(start_combat)
+            //Fixed steps so speed and collisions don't depend on the frame rate
+            //The state driver only runs in Update so the state is checked by hand here
+            .add_system_set_to_stage(
+                FixedUpdate,
+                SystemSet::new()
+                    .with_run_criteria(in_overworld)
+                    .with_system(basic_player_movement)
+                    .with_system(tile_step_movement),
+            )
+            //Movement has already run for this frame by the time these do
             .add_system_set(
                 SystemSet::on_update(GameState::Overworld)
-                    .with_system(basic_player_movement.label("movement"))
-                    .with_system(door_collision.after("movement"))
-                    .with_system(grass_collision.after("movement"))
-                    .with_system(camera_follow.after("movement")),
+                    .with_system(toggle_movement_mode)
+                    .with_system(buffer_step_input)
+                    .with_system(door_collision)
+                    .with_system(grass_collision.label("grass_collision"))
+                    .with_system(camera_follow)
+                    .with_system(player_walking)
+                    .with_system(clear_moved_flags.after("grass_collision")),
             )
             .add_system_set(
                 SystemSet::on_enter(GameState::Overworld)
@@ -54,8 +120,10 @@ impl Plugin for PlayerPlugin {
             .add_system_set(
                 SystemSet::on_exit(GameState::Overworld)
                     .with_system(hide_player)
+                    .with_system(stop_walking)
                     .with_system(reset_input),
-            );
+            )
+            .add_system_set(SystemSet::on_pause(GameState::Overworld).with_system(stop_walking));
         if ENABLE_INSPECTOR {
             app.register_inspectable::<Player>()
                 .register_inspectable::<CombatEvent>()
@@ -64,8 +132,25 @@ impl Plugin for PlayerPlugin {
     }
 }
 
-fn start_combat(mut combat_event: EventReader<CombatEvent>, mut state: ResMut<State<GameState>>) {
-    if let Some(_event) = combat_event.iter().next() {
+//Only looks at the current state, a transition that's been queued but not applied yet is
+//ignored on purpose so movement keeps going until the state actually changes, same as
+//the on_update systems in Update
+fn in_overworld(state: Res<State<GameState>>) -> ShouldRun {
+    if state.current() == &GameState::Overworld {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+fn start_combat(
+    mut combat_event: EventReader<CombatEvent>,
+    mut encounter: ResMut<Encounter>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if let Some(event) = combat_event.iter().next() {
+        encounter.enemy_type = event.enemy_type;
+        encounter.level = event.level;
         state
             .set(GameState::Combat)
             .expect("Failed to change state");
@@ -73,36 +158,39 @@ fn start_combat(mut combat_event: EventReader<CombatEvent>, mut state: ResMut<St
 }
 
 fn basic_player_movement(
-    keyboard: Res<Input<KeyCode>>,
-    time: Res<Time>,
-    mut player_query: Query<(&mut Player, &mut Transform)>,
+    actions: Res<Input<GameAction>>,
+    mut player_query: Query<(&mut Player, &mut FacingDirection, &mut Transform)>,
     wall_query: Query<&Transform, (Without<Player>, With<TileCollider>)>,
+    input_lock: Res<InputLock>,
 ) {
-    let (mut player, mut transform) = player_query.single_mut();
-    player.just_moved = false;
-    if !player.active {
+    let (mut player, mut direction, mut transform) = player_query.single_mut();
+    if player.movement_mode != MovementMode::Free {
+        return;
+    }
+    player.moving = false;
+    if input_lock.is_locked() {
         return;
     }
 
-    let to_move = player.speed * time.delta_seconds() * TILE_SIZE;
+    let to_move = player.speed * FIXED_TIMESTEP as f32 * TILE_SIZE;
 
     let mut target_y = 0.0;
-    if keyboard.pressed(KeyCode::W) {
-        player.current_direction = FacingDirection::Up;
+    if actions.pressed(GameAction::MoveUp) {
+        *direction = FacingDirection::Up;
         target_y = to_move;
     }
-    if keyboard.pressed(KeyCode::S) {
-        player.current_direction = FacingDirection::Down;
+    if actions.pressed(GameAction::MoveDown) {
+        *direction = FacingDirection::Down;
         target_y = -to_move;
     }
 
     let mut target_x = 0.0;
-    if keyboard.pressed(KeyCode::A) {
-        player.current_direction = FacingDirection::Left;
+    if actions.pressed(GameAction::MoveLeft) {
+        *direction = FacingDirection::Left;
         target_x = -to_move;
     }
-    if keyboard.pressed(KeyCode::D) {
-        player.current_direction = FacingDirection::Right;
+    if actions.pressed(GameAction::MoveRight) {
+        *direction = FacingDirection::Right;
         target_x = to_move;
     }
 
@@ -111,7 +199,7 @@ fn basic_player_movement(
     if wall_collision_check(target, &player, &wall_query) {
         transform.translation = target;
         if target_x != 0.0 {
-            player.just_moved = true;
+            player.moving = true;
         }
     }
 
@@ -120,15 +208,167 @@ fn basic_player_movement(
     if wall_collision_check(target, &player, &wall_query) {
         transform.translation = target;
         if target_y != 0.0 {
-            player.just_moved = true;
+            player.moving = true;
+        }
+    }
+    if player.moving {
+        player.moved_steps += 1;
+    }
+}
+
+fn player_walking(mut player_query: Query<(&Player, &mut Walking)>) {
+    let (player, mut walking) = player_query.single_mut();
+    walking.0 = player.moving;
+}
+
+//Nothing moves outside of the overworld so nobody should look like they're walking
+fn stop_walking(mut walking_query: Query<&mut Walking>) {
+    for mut walking in walking_query.iter_mut() {
+        walking.0 = false;
+    }
+}
+
+fn toggle_movement_mode(
+    actions: Res<Input<GameAction>>,
+    input_lock: Res<InputLock>,
+    mut player_query: Query<(&mut Player, &mut TileStep, &mut Transform)>,
+) {
+    if input_lock.is_locked() || !actions.just_pressed(GameAction::ToggleMovement) {
+        return;
+    }
+    let (mut player, mut step, mut transform) = player_query.single_mut();
+    player.movement_mode = match player.movement_mode {
+        MovementMode::Free => {
+            //Snap onto the grid so every step lands on a tile
+            transform.translation.x = (transform.translation.x / TILE_SIZE).round() * TILE_SIZE;
+            transform.translation.y = (transform.translation.y / TILE_SIZE).round() * TILE_SIZE;
+            MovementMode::TileStep
+        }
+        MovementMode::TileStep => {
+            step.cancel();
+            MovementMode::Free
+        }
+    };
+}
+
+//Last pressed wins, same priority as basic_player_movement
+fn held_direction(actions: &Input<GameAction>) -> Option<FacingDirection> {
+    let mut direction = None;
+    if actions.pressed(GameAction::MoveUp) {
+        direction = Some(FacingDirection::Up);
+    }
+    if actions.pressed(GameAction::MoveDown) {
+        direction = Some(FacingDirection::Down);
+    }
+    if actions.pressed(GameAction::MoveLeft) {
+        direction = Some(FacingDirection::Left);
+    }
+    if actions.pressed(GameAction::MoveRight) {
+        direction = Some(FacingDirection::Right);
+    }
+    direction
+}
+
+fn just_pressed_direction(actions: &Input<GameAction>) -> Option<FacingDirection> {
+    let mut direction = None;
+    if actions.just_pressed(GameAction::MoveUp) {
+        direction = Some(FacingDirection::Up);
+    }
+    if actions.just_pressed(GameAction::MoveDown) {
+        direction = Some(FacingDirection::Down);
+    }
+    if actions.just_pressed(GameAction::MoveLeft) {
+        direction = Some(FacingDirection::Left);
+    }
+    if actions.just_pressed(GameAction::MoveRight) {
+        direction = Some(FacingDirection::Right);
+    }
+    direction
+}
+
+fn direction_offset(direction: FacingDirection) -> Vec3 {
+    match direction {
+        FacingDirection::Up => Vec3::new(0.0, TILE_SIZE, 0.0),
+        FacingDirection::Down => Vec3::new(0.0, -TILE_SIZE, 0.0),
+        FacingDirection::Left => Vec3::new(-TILE_SIZE, 0.0, 0.0),
+        FacingDirection::Right => Vec3::new(TILE_SIZE, 0.0, 0.0),
+    }
+}
+
+//Fixed steps might not run on the frame a key goes down so presses are caught here
+fn buffer_step_input(
+    actions: Res<Input<GameAction>>,
+    mut player_query: Query<(&Player, &mut TileStep)>,
+    input_lock: Res<InputLock>,
+) {
+    let (player, mut step) = player_query.single_mut();
+    if player.movement_mode != MovementMode::TileStep || input_lock.is_locked() {
+        return;
+    }
+    if let Some(direction) = just_pressed_direction(&actions) {
+        step.buffered = Some(direction);
+    }
+}
+
+//After everything that reacts to this frame's movement has seen it
+fn clear_moved_flags(mut player_query: Query<(&mut Player, &mut TileStep)>) {
+    for (mut player, mut step) in player_query.iter_mut() {
+        if player.moved_steps != 0 {
+            player.moved_steps = 0;
+        }
+        if step.just_stepped {
+            step.just_stepped = false;
         }
     }
 }
 
-//Hack : https://github.com/bevyengine/bevy/issues/1700#issuecomment-803356041
-// https://bevy-cheatbook.github.io/programming/states.html#with-input
-fn reset_input(mut keyboard_input: ResMut<Input<KeyCode>>) {
-    keyboard_input.clear();
+fn tile_step_movement(
+    actions: Res<Input<GameAction>>,
+    mut player_query: Query<(
+        &mut Player,
+        &mut TileStep,
+        &mut FacingDirection,
+        &mut Transform,
+    )>,
+    wall_query: Query<&Transform, (Without<Player>, With<TileCollider>)>,
+    input_lock: Res<InputLock>,
+) {
+    let (mut player, mut step, mut facing, mut transform) = player_query.single_mut();
+    if player.movement_mode != MovementMode::TileStep {
+        return;
+    }
+    player.moving = false;
+    if input_lock.is_locked() {
+        return;
+    }
+
+    if step.moving {
+        step.timer.tick(Duration::from_secs_f64(FIXED_TIMESTEP));
+        transform.translation = step.start.lerp(step.target, step.timer.percent());
+        player.moving = true;
+        player.moved_steps += 1;
+        if !step.timer.finished() {
+            return;
+        }
+        transform.translation = step.target;
+        step.moving = false;
+        step.just_stepped = true;
+    }
+
+    let direction = match step.buffered.take().or_else(|| held_direction(&actions)) {
+        Some(direction) => direction,
+        None => return,
+    };
+    *facing = direction;
+
+    let target = transform.translation + direction_offset(direction);
+    if wall_collision_check(target, &player, &wall_query) {
+        step.start = transform.translation;
+        step.target = target;
+        //Speed is in tiles per second
+        step.timer = Timer::from_seconds(1.0 / player.speed, false);
+        step.moving = true;
+    }
 }
 
 fn wall_collision_check(
@@ -151,40 +391,67 @@ fn wall_collision_check(
 }
 
 fn grass_collision(
-    mut player_query: Query<(&Player, &mut EncounterTracker, &Transform)>,
+    mut player_query: Query<(&Player, &mut EncounterTracker, &TileStep, &Transform)>,
     wall_query: Query<(&Transform, &WildSpawn), Without<Player>>,
-    time: Res<Time>,
+    metadata: Res<MapMetadata>,
     mut commands: Commands,
     ascii: Res<AsciiSheet>, //mut exit_event: EventWriter<ExitEvent>,
+    styles: Res<TransitionStyles>,
+    mut input_lock: ResMut<InputLock>,
 ) {
-    let (player, mut encounter, player_transform) = player_query.single_mut();
-    if !player.just_moved {
+    let (player, mut encounter, step, player_transform) = player_query.single_mut();
+    //Visible monsters start their own fights
+    if player.moved_steps == 0
+        || input_lock.is_locked()
+        || metadata.encounter_mode != EncounterMode::Random
+    {
         return;
     }
 
-    for (spawn_transform, _) in wall_query.iter() {
-        let collision = collide(
+    let on_grass = wall_query.iter().any(|(spawn_transform, _)| {
+        collide(
             player_transform.translation,
             Vec2::splat(TILE_SIZE * player.hitbox_size),
             spawn_transform.translation,
             Vec2::splat(TILE_SIZE),
-        );
+        )
+        .is_some()
+    });
+    if !on_grass {
+        return;
+    }
 
-        if collision.is_some() {
-            encounter.timer.tick(time.delta());
-            break;
+    let mut rng = thread_rng();
+    let found_encounter = match player.movement_mode {
+        //Only the time spent walking counts, however many frames it was spread over
+        MovementMode::Free => {
+            let walked = player.moved_steps as f64 * FIXED_TIMESTEP;
+            encounter.timer.tick(Duration::from_secs_f64(walked));
+            encounter.timer.just_finished()
         }
-    }
+        //One roll per grass tile stepped on instead of ticking every frame
+        MovementMode::TileStep => step.just_stepped && rng.gen_bool(encounter.step_chance as f64),
+    };
 
-    if encounter.timer.just_finished() {
+    if found_encounter {
         encounter.timer.reset();
         //Get random time for next spawn
-        let mut rng = thread_rng();
         let next_time: f32 = rng.gen_range(encounter.min_time..encounter.max_time);
         encounter
             .timer
             .set_duration(Duration::from_secs_f32(next_time));
-        create_fadeout(&mut commands, ascii.clone(), CombatEvent, 0.3);
+        let event = CombatEvent {
+            enemy_type: random_enemy_type(),
+            level: rng.gen_range(metadata.min_level..=metadata.max_level),
+        };
+        input_lock.set(TRANSITION_LOCK, true);
+        create_fadeout(
+            &mut commands,
+            ascii.clone(),
+            event,
+            0.8,
+            styles.random_encounter,
+        );
     }
 }
 
@@ -225,13 +492,15 @@ fn show_player(
 }
 
 fn door_collision(
-    mut player_query: Query<(&mut Player, &Transform)>,
+    player_query: Query<(&Player, &Transform)>,
     wall_query: Query<(&Transform, &Door), Without<Player>>,
     mut commands: Commands,
     ascii: Res<AsciiSheet>, //mut exit_event: EventWriter<ExitEvent>,
+    styles: Res<TransitionStyles>,
+    mut input_lock: ResMut<InputLock>,
 ) {
-    let (mut player, player_transform) = player_query.single_mut();
-    if !player.active {
+    let (player, player_transform) = player_query.single();
+    if input_lock.is_locked() {
         return;
     }
 
@@ -245,18 +514,22 @@ fn door_collision(
         );
 
         if collision.is_some() {
-            player.active = false;
-            create_fadeout(&mut commands, ascii.clone(), ExitEvent(door.clone()), 0.3);
+            input_lock.set(TRANSITION_LOCK, true);
+            create_fadeout(
+                &mut commands,
+                ascii.clone(),
+                ExitEvent(door.clone()),
+                0.3,
+                styles.door,
+            );
+            return;
         }
     }
 }
 
-pub fn spawn_player(
-    mut commands: Commands,
-    graphics: Res<GraphicsHandles>,
-    animations: Res<PlayerAnimations>,
-) {
-    let mut sprite = TextureAtlasSprite::new(animations.walk_down[0]);
+pub fn spawn_player(mut commands: Commands, graphics: Res<GraphicsHandles>) {
+    //Frame gets set by the animator
+    let mut sprite = TextureAtlasSprite::new(0);
     sprite.custom_size = Some(Vec2::splat(TILE_SIZE));
 
     commands
@@ -271,30 +544,61 @@ pub fn spawn_player(
         })
         .insert(Name::new("Player"))
         .insert(Player {
-            current_direction: FacingDirection::Down,
             speed: 6.0,
             hitbox_size: 0.90,
-            just_moved: false,
-            active: true,
-        })
-        .insert(AnimatedSprite {
-            current_frame: 0,
-            timer: Timer::from_seconds(0.2, true),
+            moved_steps: 0,
+            moving: false,
+            movement_mode: MovementMode::Free,
         })
+        .insert(FacingDirection::Down)
+        .insert(graphics.character_animator(PLAYER_CHARACTER))
+        .insert(Walking::default())
+        .insert(IdleFidget::new(5.0))
         .insert(EncounterTracker {
             timer: Timer::from_seconds(1.0, true),
             min_time: 0.5,
             max_time: 2.5,
-        });
+            step_chance: 0.15,
+        })
+        .insert(TileStep::default());
 }
 
 fn camera_follow(
     mut camera_query: Query<&mut Transform, (With<Camera>, Without<Player>)>,
     player_query: Query<(&Player, &Transform)>,
+    mut follow: ResMut<CameraFollow>,
+    bounds: Res<MapBounds>,
+    view: Res<ViewSize>,
+    time: Res<Time>,
 ) {
     let mut cam_transform = camera_query.single_mut();
     let (_, player_transform) = player_query.single();
 
-    cam_transform.translation.x = player_transform.translation.x;
-    cam_transform.translation.y = player_transform.translation.y;
+    let camera = cam_transform.translation.truncate();
+    let player = player_transform.translation.truncate();
+    //New maps start with the camera already in place
+    let snap = follow.snap || bounds.is_changed();
+    let target = if snap {
+        player
+    } else {
+        //Only moves once the player leaves the dead zone, then just enough to keep them in it
+        let offset = player - camera;
+        player - offset.clamp(-follow.dead_zone, follow.dead_zone)
+    };
+    let target = clamp_to_bounds(target, &bounds, &view);
+    let position = if snap || follow.smoothing <= 0.0 {
+        target
+    } else {
+        //Closes the same fraction of the gap each second whatever the frame rate
+        camera.lerp(
+            target,
+            1.0 - (-follow.smoothing * time.delta_seconds()).exp(),
+        )
+    };
+
+    cam_transform.translation.x = position.x;
+    cam_transform.translation.y = position.y;
+    if follow.snap {
+        follow.snap = false;
+    }
 }
@@ -0,0 +1,237 @@
+use bevy::prelude::*;
+use std::path::Path;
+
+use crate::ascii::{spawn_ascii_text, AsciiSheet, TextWidget};
+use crate::camera::{Anchor, ScreenAnchor};
+use crate::input::{
+    binding_name, is_bindable, reset_input, Binding, GameAction, InputMap, ALL_ACTIONS,
+    CONTROLS_PATH,
+};
+use crate::nine_sprite::{NineSpriteIndices, NineSpriteTheme};
+use crate::ui::{spawn_panel, spawn_scroll_list, Focused, ListConfirmed, ScrollList};
+use crate::{GameState, TILE_SIZE};
+
+//Rows are too long for the screen at full size
+const MENU_SCALE: f32 = 0.75;
+const MENU_WIDTH: f32 = 43.0;
+
+const VISIBLE_ROWS: usize = 6;
+//Waiting for a binding gives up after this long, so every key stays bindable
+//without one being taken to back out
+const REBIND_TIMEOUT: f32 = 5.0;
+
+#[derive(Component)]
+struct RebindMenu {
+    list: Entity,
+    hint: Entity,
+    //Waiting for the next key or button to bind to the selected action
+    waiting: bool,
+    wait_timer: Timer,
+}
+
+pub struct RebindMenuPlugin;
+
+impl Plugin for RebindMenuPlugin {
+    fn build(&self, app: &mut App) {
+        //Opened from the options menu
+        app.add_system_set(
+            SystemSet::on_enter(GameState::Rebind)
+                .with_system(create_rebind_menu)
+                .with_system(reset_input),
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Rebind)
+                .with_system(rebind_menu_input.after("ui_navigation")),
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Rebind)
+                .with_system(delete_rebind_menu)
+                .with_system(reset_input),
+        );
+    }
+}
+
+fn binding_names(input_map: &InputMap, action: GameAction) -> (String, String) {
+    let mut key = String::new();
+    let mut button = String::new();
+    for binding in input_map.bindings(action) {
+        match binding {
+            Binding::Key(code) => key = format!("{:?}", code),
+            Binding::Button(button_type) => button = format!("{:?}", button_type),
+        }
+    }
+    (key, button)
+}
+
+fn rebind_row(input_map: &InputMap, action: GameAction, waiting: bool) -> String {
+    let (key, button) = binding_names(input_map, action);
+    let key = if waiting { "..." } else { key.as_str() };
+    format!("{:<15}{:<11}{}", format!("{:?}", action), key, button)
+}
+
+fn rebind_rows(input_map: &InputMap) -> Vec<String> {
+    ALL_ACTIONS
+        .iter()
+        .map(|action| rebind_row(input_map, *action, false))
+        .collect()
+}
+
+fn hint_text(waiting: bool) -> &'static str {
+    if waiting {
+        "  Press a key or button, or wait to cancel"
+    } else {
+        "  Confirm to rebind, Cancel to save and exit"
+    }
+}
+
+fn create_rebind_menu(mut commands: Commands, ascii: Res<AsciiSheet>, input_map: Res<InputMap>) {
+    //Header, the list and its border, then the hint, all inside the panel's border
+    let width = MENU_WIDTH * TILE_SIZE;
+    let height = (VISIBLE_ROWS as f32 + 6.0) * TILE_SIZE;
+    let theme = NineSpriteTheme {
+        indices: NineSpriteIndices::DOUBLE,
+        shadow_color: Some(Color::rgba(0.0, 0.0, 0.0, 0.5)),
+        ..Default::default()
+    };
+    let panel = spawn_panel(
+        &mut commands,
+        ascii.clone(),
+        theme,
+        Vec2::new(width, height),
+        Vec3::ZERO,
+    );
+
+    //Lines up with the text of the list's rows
+    let header = spawn_ascii_text(
+        &mut commands,
+        &format!("  {:<15}{:<11}{}", "Controls", "Key", "Gamepad"),
+        Vec3::new(
+            -width / 2.0 + 2.0 * TILE_SIZE,
+            height / 2.0 - 1.5 * TILE_SIZE,
+            0.0,
+        ),
+    );
+    let list = spawn_scroll_list(
+        &mut commands,
+        ascii.clone(),
+        NineSpriteTheme {
+            fill_color: None,
+            ..Default::default()
+        },
+        rebind_rows(&input_map),
+        VISIBLE_ROWS,
+        width - 2.0 * TILE_SIZE,
+        Vec3::new(0.0, 0.0, 0.1),
+    );
+    commands.entity(list).insert(Focused);
+    let hint = spawn_ascii_text(
+        &mut commands,
+        hint_text(false),
+        Vec3::new(
+            -width / 2.0 + TILE_SIZE,
+            -height / 2.0 + 1.5 * TILE_SIZE,
+            0.0,
+        ),
+    );
+
+    commands
+        .entity(panel)
+        .insert(Name::new("RebindMenu"))
+        .insert(RebindMenu {
+            list: list,
+            hint: hint,
+            waiting: false,
+            wait_timer: Timer::from_seconds(REBIND_TIMEOUT, false),
+        })
+        .insert(ScreenAnchor {
+            anchor: Anchor::Center,
+            offset: Vec2::ZERO,
+        })
+        //Over the world but under screen fades
+        .insert(Transform {
+            translation: Vec3::new(0.0, 0.0, 950.0),
+            scale: Vec3::splat(MENU_SCALE),
+            ..Default::default()
+        })
+        .push_children(&[header, list, hint]);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn rebind_menu_input(
+    mut commands: Commands,
+    mut menu_query: Query<&mut RebindMenu>,
+    mut list_query: Query<&mut ScrollList>,
+    mut text_query: Query<&mut TextWidget>,
+    mut confirmed: EventReader<ListConfirmed>,
+    actions: Res<Input<GameAction>>,
+    keyboard: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    mut input_map: ResMut<InputMap>,
+    mut state: ResMut<State<GameState>>,
+    time: Res<Time>,
+) {
+    let mut menu = menu_query.single_mut();
+    let mut list = list_query
+        .get_mut(menu.list)
+        .expect("Rebind menu has no list");
+    let list_entity = menu.list;
+    let confirmed = confirmed.iter().any(|event| event.list == list_entity);
+    let action = ALL_ACTIONS[list.selected];
+
+    let mut hint = hint_text(false).to_string();
+    if menu.waiting {
+        menu.wait_timer.tick(time.delta());
+        //Keys the controls file can't store are ignored rather than lost on the next launch
+        let pressed = keyboard
+            .get_just_pressed()
+            .map(|key| Binding::Key(*key))
+            .chain(
+                buttons
+                    .get_just_pressed()
+                    .map(|button| Binding::Button(button.1)),
+            )
+            .find(|binding| is_bindable(*binding));
+        if let Some(binding) = pressed {
+            input_map.rebind(action, binding);
+            let others = input_map.other_actions_bound_to(action, binding);
+            if !others.is_empty() {
+                let names: Vec<String> =
+                    others.iter().map(|other| format!("{:?}", other)).collect();
+                hint = format!(
+                    "  {} also does {}",
+                    binding_name(&binding),
+                    names.join(", ")
+                );
+            }
+        } else if !menu.wait_timer.finished() {
+            return;
+        }
+        menu.waiting = false;
+        list.items = rebind_rows(&input_map);
+        commands.entity(menu.list).insert(Focused);
+    } else if actions.just_pressed(GameAction::Cancel) {
+        input_map.save(Path::new(CONTROLS_PATH));
+        state.pop().expect("Failed to change state");
+        return;
+    } else if confirmed {
+        //The list would otherwise move with the key being bound
+        menu.waiting = true;
+        menu.wait_timer.reset();
+        let selected = list.selected;
+        list.items[selected] = rebind_row(&input_map, action, true);
+        commands.entity(menu.list).remove::<Focused>();
+        hint = hint_text(true).to_string();
+    } else {
+        return;
+    }
+
+    if let Ok(mut hint_widget) = text_query.get_mut(menu.hint) {
+        hint_widget.text = hint;
+    }
+}
+
+fn delete_rebind_menu(mut commands: Commands, menu_query: Query<Entity, With<RebindMenu>>) {
+    for menu in menu_query.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+}
@@ -1,78 +1,248 @@
 use bevy::prelude::*;
+use bevy::transform::TransformSystem;
 use bevy_inspector_egui::Inspectable;
+use rand::{thread_rng, Rng};
+use std::f32::consts::PI;
 
 use crate::{
     ascii::{spawn_ascii_sprite, AsciiSheet},
-    player::Player,
+    camera::ViewSize,
+    input::InputLock,
+    tween::{Ease, Tween, TweenCompleted, TweenTarget, Tweener},
+    RESOLUTION, TILE_SIZE,
 };
 
+const FADE_MIDPOINT: &str = "fade_midpoint";
+const FADE_DONE: &str = "fade_done";
+//Held on the InputLock while any transition is on screen
+pub const TRANSITION_LOCK: &str = "transition";
+//Size of each square in the grid based transitions
+const COVER_TILE: f32 = 2.0 * TILE_SIZE;
+//Portion of each half a single square takes to go from clear to black
+const COVER_POP: f32 = 0.25;
+
+pub struct ScreenTransitionPlugin;
+
+impl Plugin for ScreenTransitionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TransitionStyles>()
+            .add_system_to_stage(CoreStage::PreUpdate, lock_input_during_transitions)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                follow_camera.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum TransitionStyle {
+    //The whole screen at once
+    Fade,
+    //A grid of squares that each go black on their own
+    Cover(CoverPattern),
+}
+
+//Which squares go first in a cover transition
+#[derive(Clone, Copy)]
+pub enum CoverPattern {
+    //Sweeps left to right
+    Wipe,
+    //Closes in on the middle of the screen then opens back up
+    Iris,
+    Swirl,
+    //Squares go black in a random order
+    Dissolve,
+}
+
+//Which transition plays for each way of changing scenes
+pub struct TransitionStyles {
+    pub door: TransitionStyle,
+    pub random_encounter: TransitionStyle,
+    pub wild_encounter: TransitionStyle,
+    pub victory: TransitionStyle,
+    pub flee: TransitionStyle,
+}
+
+impl Default for TransitionStyles {
+    fn default() -> Self {
+        TransitionStyles {
+            door: TransitionStyle::Fade,
+            random_encounter: TransitionStyle::Cover(CoverPattern::Swirl),
+            wild_encounter: TransitionStyle::Cover(CoverPattern::Iris),
+            victory: TransitionStyle::Cover(CoverPattern::Dissolve),
+            flee: TransitionStyle::Cover(CoverPattern::Wipe),
+        }
+    }
+}
+
 #[derive(Component, Inspectable)]
 pub struct ScreenFade<T> {
-    pub alpha: f32,
-    pub sent: bool,
     //Fires when screen is completely black
     pub event: T,
 }
 
+//Root of any transition, kept in front of the camera
+#[derive(Component)]
+struct ScreenTransition;
+
 //XXX how to assert that T has a system registered to match the fadeout
 pub fn create_fadeout<T: 'static + std::marker::Send + std::marker::Sync + Clone>(
     commands: &mut Commands,
     ascii: AsciiSheet,
     event: T,
     fade_time: f32,
+    style: TransitionStyle,
 ) {
-    let screen_fade = spawn_ascii_sprite(
-        commands,
-        &ascii,
-        0,
-        Color::rgba(0.0, 0.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 999.9),
-        Vec3::splat(100.0),
-    );
+    let half = fade_time / 2.0;
+    let cover = match style {
+        TransitionStyle::Fade => vec![spawn_fade(commands, &ascii, half)],
+        TransitionStyle::Cover(pattern) => spawn_cover_grid(commands, &ascii, half, pattern),
+    };
+
     commands
-        .entity(screen_fade)
-        .insert(ScreenFade {
-            alpha: 0.0,
-            sent: false,
-            event: event,
-        })
-        .insert(Timer::from_seconds(fade_time, false))
-        .insert(Name::new("Fadeout"));
+        .spawn()
+        .insert(ScreenFade { event: event })
+        .insert(ScreenTransition)
+        .insert(
+            Tweener::new(Tween::delay(half).with_event(FADE_MIDPOINT))
+                .then(Tween::delay(half).with_event(FADE_DONE)),
+        )
+        .insert(Name::new("Fadeout"))
+        //Needs transforms for parent heirarchy system to work
+        .insert(Transform::from_translation(Vec3::new(0.0, 0.0, 999.9)))
+        .insert(GlobalTransform::default())
+        .push_children(&cover);
+}
+
+fn color_tween(start: Color, end: Color, duration: f32, ease: Ease) -> Tween {
+    Tween::new(TweenTarget::SpriteColor { start, end }, duration, ease)
+}
+
+fn spawn_fade(commands: &mut Commands, ascii: &AsciiSheet, half: f32) -> Entity {
+    let clear = Color::rgba(0.0, 0.0, 0.0, 0.0);
+    let black = Color::rgba(0.0, 0.0, 0.0, 1.0);
+    let fade = spawn_ascii_sprite(commands, ascii, 0, clear, Vec3::ZERO, Vec3::splat(100.0));
+    commands.entity(fade).insert(
+        Tweener::new(color_tween(clear, black, half, Ease::QuadIn)).then(color_tween(
+            black,
+            clear,
+            half,
+            Ease::QuadOut,
+        )),
+    );
+    fade
+}
+
+//When a square covers and uncovers the screen, 0 is first and 1 is last
+fn cover_order(pattern: CoverPattern, position: Vec2, max_distance: f32) -> (f32, f32) {
+    let distance = (position.length() / max_distance).min(1.0);
+    match pattern {
+        CoverPattern::Wipe => {
+            let across = (position.x / RESOLUTION + 1.0) / 2.0;
+            //Keeps sweeping the same way on the way out
+            (across, across)
+        }
+        CoverPattern::Iris => (1.0 - distance, distance),
+        CoverPattern::Swirl => {
+            let angle = (position.y.atan2(position.x) + PI) / (2.0 * PI);
+            let order = (angle + 1.0 - distance) / 2.0;
+            (order, order)
+        }
+        CoverPattern::Dissolve => {
+            let mut rng = thread_rng();
+            (rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0))
+        }
+    }
+}
+
+fn spawn_cover_grid(
+    commands: &mut Commands,
+    ascii: &AsciiSheet,
+    half: f32,
+    pattern: CoverPattern,
+) -> Vec<Entity> {
+    let clear = Color::rgba(0.0, 0.0, 0.0, 0.0);
+    let black = Color::rgba(0.0, 0.0, 0.0, 1.0);
+    let pop = half * COVER_POP;
+    let spread = half - pop;
+
+    let columns = (2.0 * RESOLUTION / COVER_TILE).ceil() as i32 + 1;
+    let rows = (2.0 / COVER_TILE).ceil() as i32 + 1;
+    let max_distance = Vec2::new(RESOLUTION, 1.0).length();
+
+    let mut squares = Vec::new();
+    for x in 0..columns {
+        for y in 0..rows {
+            let position = Vec2::new(
+                -RESOLUTION + x as f32 * COVER_TILE,
+                -1.0 + y as f32 * COVER_TILE,
+            );
+            let (cover, uncover) = cover_order(pattern, position, max_distance);
+            let square = spawn_ascii_sprite(
+                commands,
+                ascii,
+                0,
+                clear,
+                position.extend(0.0),
+                //Slightly oversized so there are no seams between squares
+                Vec3::splat(COVER_TILE / TILE_SIZE * 1.05),
+            );
+            commands.entity(square).insert(
+                Tweener::new(Tween::delay(cover * spread))
+                    .then(color_tween(clear, black, pop, Ease::Linear))
+                    //Everything is black at the midpoint
+                    .then(Tween::delay(spread - cover * spread + uncover * spread))
+                    .then(color_tween(black, clear, pop, Ease::Linear)),
+            );
+            squares.push(square);
+        }
+    }
+    squares
+}
+
+//Whoever starts a transition also locks right away so nothing else fires the same frame
+fn lock_input_during_transitions(
+    transition_query: Query<(), With<ScreenTransition>>,
+    mut input_lock: ResMut<InputLock>,
+) {
+    let transitioning = transition_query.iter().next().is_some();
+    input_lock.set(TRANSITION_LOCK, transitioning);
+}
+
+//Covers are laid out for a view one unit tall so they're scaled up to fit bigger windows
+fn follow_camera(
+    mut transition_query: Query<&mut Transform, With<ScreenTransition>>,
+    camera_query: Query<&Transform, (With<Camera>, Without<ScreenTransition>)>,
+    view: Res<ViewSize>,
+) {
+    let camera = match camera_query.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    for mut transform in transition_query.iter_mut() {
+        transform.translation.x = camera.translation.x;
+        transform.translation.y = camera.translation.y;
+        transform.scale = Vec3::new(view.half_height, view.half_height, 1.0);
+    }
 }
 
 //T is the event to fire when the screen is compeletly black
 pub fn fadeout<T: 'static + std::marker::Send + std::marker::Sync + Clone>(
     mut commands: Commands,
-    mut player_query: Query<&mut Player>,
-    mut fade_query: Query<(
-        Entity,
-        &mut ScreenFade<T>,
-        &mut Timer,
-        &mut TextureAtlasSprite,
-    )>,
-    time: Res<Time>,
+    fade_query: Query<&ScreenFade<T>>,
+    mut completed: EventReader<TweenCompleted>,
     mut event: EventWriter<T>,
 ) {
-    let mut player = player_query.single_mut();
-    for (entity, mut fade, mut timer, mut sprite) in fade_query.iter_mut() {
-        player.active = false;
-        timer.tick(time.delta());
-
-        if timer.percent() < 0.5 {
-            fade.alpha = timer.percent() * 2.0;
-        } else {
-            fade.alpha = timer.percent_left() * 2.0;
-        }
-        sprite.color.set_a(fade.alpha);
-
-        if timer.percent() > 0.5 && !fade.sent {
-            event.send(fade.event.clone());
-            fade.sent = true;
-        }
-
-        if timer.just_finished() {
-            commands.entity(entity).despawn_recursive();
-            player.active = true;
+    for tween in completed.iter() {
+        //Other fadeout::<T> systems handle their own fades
+        let fade = match fade_query.get(tween.entity) {
+            Ok(fade) => fade,
+            Err(_) => continue,
+        };
+        match tween.name.as_str() {
+            FADE_MIDPOINT => event.send(fade.event.clone()),
+            FADE_DONE => commands.entity(tween.entity).despawn_recursive(),
+            _ => {}
         }
     }
 }
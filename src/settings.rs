@@ -0,0 +1,292 @@
+use bevy::prelude::*;
+use bevy::window::WindowMode;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use crate::audio::AudioState;
+use crate::RESOLUTION;
+
+pub const SETTINGS_PATH: &str = "settings.txt";
+//Screen height at 1x, 20 tiles of 16 pixels
+pub const BASE_HEIGHT: f32 = 320.0;
+pub const MAX_WINDOW_SCALE: u32 = 4;
+//None leaves the frame rate uncapped
+pub const FRAME_CAPS: [Option<u32>; 5] = [Some(30), Some(60), Some(120), Some(144), None];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextSpeed {
+    Slow,
+    Normal,
+    Fast,
+    Instant,
+}
+
+pub const ALL_TEXT_SPEEDS: [TextSpeed; 4] = [
+    TextSpeed::Slow,
+    TextSpeed::Normal,
+    TextSpeed::Fast,
+    TextSpeed::Instant,
+];
+
+impl TextSpeed {
+    //None shows the whole message at once
+    pub fn chars_per_second(&self) -> Option<f32> {
+        match self {
+            TextSpeed::Slow => Some(20.0),
+            TextSpeed::Normal => Some(40.0),
+            TextSpeed::Fast => Some(80.0),
+            TextSpeed::Instant => None,
+        }
+    }
+}
+
+//Saved whenever the options screen is closed
+//Read in main before the window is made so it opens at the right size
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub fullscreen: bool,
+    //Window height is BASE_HEIGHT times this
+    pub window_scale: u32,
+    pub text_speed: TextSpeed,
+    //Frames per second, None for no limit besides vsync
+    pub frame_cap: Option<u32>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            master_volume: 0.5,
+            music_volume: 1.0,
+            sfx_volume: 1.0,
+            fullscreen: false,
+            window_scale: 3,
+            text_speed: TextSpeed::Normal,
+            frame_cap: Some(120),
+        }
+    }
+}
+
+impl Settings {
+    pub fn window_size(&self) -> Vec2 {
+        let height = BASE_HEIGHT * self.window_scale as f32;
+        Vec2::new(height * RESOLUTION, height)
+    }
+
+    pub fn window_mode(&self) -> WindowMode {
+        if self.fullscreen {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        }
+    }
+
+    pub fn window_descriptor(&self) -> WindowDescriptor {
+        let size = self.window_size();
+        WindowDescriptor {
+            width: size.x,
+            height: size.y,
+            title: "Monster Fighter".to_string(),
+            vsync: true,
+            resizable: true,
+            mode: self.window_mode(),
+            ..Default::default()
+        }
+    }
+
+    //Missing settings keep their defaults, bad lines are skipped
+    pub fn load(path: &Path) -> Settings {
+        let mut settings = Settings::default();
+        let input = match File::open(path) {
+            Ok(input) => input,
+            Err(_) => return settings,
+        };
+        for line in BufReader::new(input).lines().flatten() {
+            if line.starts_with('/') || line.trim().is_empty() {
+                continue;
+            }
+            if settings.parse_line(&line).is_none() {
+                println!("Bad settings formatting: {}", line);
+            }
+        }
+        settings
+    }
+
+    fn parse_line(&mut self, line: &str) -> Option<()> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.len() != 2 {
+            return None;
+        }
+        let value = words[1];
+        match words[0] {
+            "master_volume" => self.master_volume = value.parse::<f32>().ok()?.clamp(0.0, 1.0),
+            "music_volume" => self.music_volume = value.parse::<f32>().ok()?.clamp(0.0, 1.0),
+            "sfx_volume" => self.sfx_volume = value.parse::<f32>().ok()?.clamp(0.0, 1.0),
+            "fullscreen" => self.fullscreen = value.parse().ok()?,
+            "window_scale" => {
+                self.window_scale = value.parse::<u32>().ok()?.clamp(1, MAX_WINDOW_SCALE)
+            }
+            "text_speed" => {
+                self.text_speed = *ALL_TEXT_SPEEDS
+                    .iter()
+                    .find(|speed| format!("{:?}", speed) == value)?
+            }
+            "frame_cap" if value == "off" => self.frame_cap = None,
+            "frame_cap" => self.frame_cap = Some(value.parse::<u32>().ok()?.max(1)),
+            _ => return None,
+        }
+        Some(())
+    }
+
+    pub fn save(&self, path: &Path) {
+        let mut output = match File::create(path) {
+            Ok(output) => output,
+            Err(err) => {
+                println!("Failed to save settings: {}", err);
+                return;
+            }
+        };
+        let contents = format!(
+            "//setting value\n\
+             master_volume {:.2}\n\
+             music_volume {:.2}\n\
+             sfx_volume {:.2}\n\
+             fullscreen {}\n\
+             window_scale {}\n\
+             text_speed {:?}\n\
+             frame_cap {}\n",
+            self.master_volume,
+            self.music_volume,
+            self.sfx_volume,
+            self.fullscreen,
+            self.window_scale,
+            self.text_speed,
+            self.frame_cap
+                .map_or("off".to_string(), |cap| cap.to_string())
+        );
+        if let Err(err) = output.write_all(contents.as_bytes()) {
+            println!("Failed to save settings: {}", err);
+        }
+    }
+}
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(apply_settings);
+    }
+}
+
+//Also runs on the first frame so the audio starts at the saved volumes
+fn apply_settings(
+    settings: Res<Settings>,
+    mut audio_state: ResMut<AudioState>,
+    mut windows: ResMut<Windows>,
+    mut applied: Local<Option<Settings>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    audio_state.main_volume = settings.master_volume;
+    audio_state.music_volume = settings.music_volume;
+    //UI sounds count as effects as far as the options go
+    audio_state.sfx_volume = settings.sfx_volume;
+    audio_state.ui_volume = settings.sfx_volume;
+
+    //The window starts out matching the settings and might be resized by hand after that,
+    //so it's only touched when the window settings themselves change
+    let window_changed = match &*applied {
+        Some(old) => {
+            old.fullscreen != settings.fullscreen || old.window_scale != settings.window_scale
+        }
+        None => false,
+    };
+    *applied = Some(settings.clone());
+    if !window_changed {
+        return;
+    }
+    if let Some(window) = windows.get_primary_mut() {
+        window.set_mode(settings.window_mode());
+        if !settings.fullscreen {
+            let size = settings.window_size();
+            window.set_resolution(size.x, size.y);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    fn parsed(line: &str) -> Option<Settings> {
+        let mut settings = Settings::default();
+        settings.parse_line(line).map(|_| settings)
+    }
+
+    #[test]
+    fn parses_each_setting() {
+        assert_eq!(parsed("master_volume 0.25").unwrap().master_volume, 0.25);
+        assert_eq!(parsed("music_volume 0").unwrap().music_volume, 0.0);
+        assert_eq!(parsed("sfx_volume 0.75").unwrap().sfx_volume, 0.75);
+        assert!(parsed("fullscreen true").unwrap().fullscreen);
+        assert_eq!(parsed("window_scale 2").unwrap().window_scale, 2);
+        assert_eq!(
+            parsed("text_speed Instant").unwrap().text_speed,
+            TextSpeed::Instant
+        );
+        assert_eq!(parsed("frame_cap 60").unwrap().frame_cap, Some(60));
+        assert_eq!(parsed("frame_cap off").unwrap().frame_cap, None);
+    }
+
+    #[test]
+    fn out_of_range_values_are_clamped() {
+        assert_eq!(parsed("master_volume 3").unwrap().master_volume, 1.0);
+        assert_eq!(parsed("sfx_volume -1").unwrap().sfx_volume, 0.0);
+        assert_eq!(parsed("window_scale 0").unwrap().window_scale, 1);
+        assert_eq!(
+            parsed("window_scale 99").unwrap().window_scale,
+            MAX_WINDOW_SCALE
+        );
+        assert_eq!(parsed("frame_cap 0").unwrap().frame_cap, Some(1));
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        assert!(parsed("master_volume").is_none());
+        assert!(parsed("master_volume loud").is_none());
+        assert!(parsed("fullscreen yes").is_none());
+        assert!(parsed("text_speed Ludicrous").is_none());
+        assert!(parsed("frame_cap -5").is_none());
+        assert!(parsed("brightness 1").is_none());
+        assert!(parsed("window_scale 2 3").is_none());
+    }
+
+    #[test]
+    fn save_then_load_round_trips() {
+        let path = temp_path("settings_round_trip.txt");
+        let settings = Settings {
+            master_volume: 0.25,
+            music_volume: 0.5,
+            sfx_volume: 0.75,
+            fullscreen: true,
+            window_scale: 2,
+            text_speed: TextSpeed::Fast,
+            frame_cap: None,
+        };
+        settings.save(&path);
+        let loaded = Settings::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, settings);
+    }
+
+    #[test]
+    fn missing_file_loads_defaults() {
+        let path = temp_path("settings_missing.txt");
+        assert_eq!(Settings::load(&path), Settings::default());
+    }
+}
@@ -0,0 +1,6 @@
+use std::path::PathBuf;
+
+//Somewhere for tests to write files, named per test and per run so tests can run in parallel
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("monster_fighter_{}_{}", std::process::id(), name))
+}
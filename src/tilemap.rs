@@ -1,4 +1,5 @@
 use bevy::prelude::*;
+use bevy::utils::HashSet;
 use bevy_inspector_egui::{Inspectable, RegisterInspectable};
 use std::collections::VecDeque;
 use std::fs::File;
@@ -7,7 +8,8 @@ use std::path::Path;
 
 use crate::debug::ENABLE_INSPECTOR;
 use crate::graphics::GraphicsHandles;
-use crate::player::Player;
+use crate::npc::{parse_npc, spawn_npc, NpcSpawn};
+use crate::player::{Player, TileStep};
 use crate::screen_fadeout::{fadeout, ScreenFade};
 use crate::GameState;
 use crate::TILE_SIZE;
@@ -36,6 +38,104 @@ pub struct TileCollider;
 //TODO needs some stats
 pub struct WildSpawn;
 
+//Walkable layout of the loaded map for pathfinding, x grows right and y grows down
+//matching door coordinates in the map files
+pub struct MapGrid {
+    pub width: i32,
+    pub height: i32,
+    colliders: HashSet<IVec2>,
+    wild: HashSet<IVec2>,
+}
+
+impl MapGrid {
+    pub fn is_walkable(&self, tile: IVec2) -> bool {
+        tile.x >= 0
+            && tile.y >= 0
+            && tile.x < self.width
+            && tile.y < self.height
+            && !self.colliders.contains(&tile)
+    }
+
+    pub fn is_wild(&self, tile: IVec2) -> bool {
+        self.wild.contains(&tile)
+    }
+
+    pub fn wild_tiles(&self) -> impl Iterator<Item = &IVec2> {
+        self.wild.iter()
+    }
+
+    //Same characters as the map files, without any comments
+    #[cfg(test)]
+    pub fn from_rows(rows: &[&str]) -> MapGrid {
+        let mut grid = MapGrid {
+            width: 0,
+            height: rows.len() as i32,
+            colliders: HashSet::default(),
+            wild: HashSet::default(),
+        };
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                let tile = IVec2::new(x as i32, y as i32);
+                grid.width = grid.width.max(tile.x + 1);
+                if is_collider(c) {
+                    grid.colliders.insert(tile);
+                }
+                if c == 'G' {
+                    grid.wild.insert(tile);
+                }
+            }
+        }
+        grid
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum EncounterMode {
+    //Invisible rolls while walking through grass
+    Random,
+    //Monsters roam the grass and fights start by touching them
+    Visible,
+}
+
+//Outside edges of the loaded map in world units, replaced whenever a map is loaded
+pub struct MapBounds {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+//Settings from the map file comments, replaced whenever a map is loaded
+//Format: /encounters <random|visible> <min level> <max level>
+//        /music <path from assets>
+pub struct MapMetadata {
+    pub encounter_mode: EncounterMode,
+    pub min_level: i64,
+    pub max_level: i64,
+    //Loops while on the map, silence if there isn't any
+    pub music: Option<String>,
+}
+
+impl Default for MapMetadata {
+    fn default() -> Self {
+        MapMetadata {
+            encounter_mode: EncounterMode::Random,
+            min_level: 1,
+            max_level: 3,
+            music: None,
+        }
+    }
+}
+
+pub fn world_to_grid(translation: Vec3) -> IVec2 {
+    IVec2::new(
+        (translation.x / TILE_SIZE).round() as i32,
+        (-translation.y / TILE_SIZE).round() as i32,
+    )
+}
+
+pub fn grid_to_world(tile: IVec2, z: f32) -> Vec3 {
+    Vec3::new(TILE_SIZE * tile.x as f32, -TILE_SIZE * tile.y as f32, z)
+}
+
 pub struct TileMapPlugin;
 
 impl Plugin for TileMapPlugin {
@@ -78,7 +178,7 @@ fn load_exit(
     mut commands: Commands,
     graphics: Res<GraphicsHandles>,
     map_query: Query<Entity, With<Map>>,
-    mut player_query: Query<(&mut Transform, &Player)>,
+    mut player_query: Query<(&mut Transform, &mut TileStep), With<Player>>,
     mut exit_event: EventReader<ExitEvent>,
 ) {
     if let Some(event) = exit_event.iter().next() {
@@ -89,7 +189,9 @@ fn load_exit(
             commands.entity(map).despawn_recursive();
         }
         load_map(commands, graphics, Path::new(&event.0.path));
-        let (mut transform, _) = player_query.single_mut();
+        let (mut transform, mut step) = player_query.single_mut();
+        //Don't finish a step started in the old map
+        step.cancel();
         transform.translation.x = TILE_SIZE * event.0.new_x as f32;
         transform.translation.y = -TILE_SIZE * event.0.new_y as f32;
     }
@@ -99,6 +201,14 @@ fn load_map(mut commands: Commands, graphics: Res<GraphicsHandles>, path: &Path)
     let input = File::open(path).expect("No map found");
     let mut tiles = Vec::new();
     let mut exits = VecDeque::new();
+    let mut npcs = Vec::new();
+    let mut grid = MapGrid {
+        width: 0,
+        height: 0,
+        colliders: HashSet::default(),
+        wild: HashSet::default(),
+    };
+    let mut metadata = MapMetadata::default();
 
     let mut comment_counter = 0;
     for (y, line) in BufReader::new(input).lines().enumerate() {
@@ -107,9 +217,18 @@ fn load_map(mut commands: Commands, graphics: Res<GraphicsHandles>, path: &Path)
                 if c == '/' {
                     // comment
                     comment_counter += 1;
-                    parse_comment(&line, &mut exits);
+                    parse_comment(&line, &mut exits, &mut npcs, &mut metadata);
                     break;
                 } else {
+                    let tile = IVec2::new(x as i32, y as i32 - comment_counter);
+                    grid.width = grid.width.max(tile.x + 1);
+                    grid.height = grid.height.max(tile.y + 1);
+                    if is_collider(c) {
+                        grid.colliders.insert(tile);
+                    }
+                    if c == 'G' {
+                        grid.wild.insert(tile);
+                    }
                     tiles.push(parse_tile(
                         &mut commands,
                         &graphics,
@@ -124,6 +243,21 @@ fn load_map(mut commands: Commands, graphics: Res<GraphicsHandles>, path: &Path)
     }
     //TODO assert exits is empty
 
+    for npc in npcs {
+        tiles.push(spawn_npc(&mut commands, &graphics, npc));
+    }
+    //Tiles are centered on their grid position so the edges are half a tile out
+    let half_tile = TILE_SIZE / 2.0;
+    commands.insert_resource(MapBounds {
+        min: Vec2::new(
+            -half_tile,
+            -TILE_SIZE * (grid.height - 1) as f32 - half_tile,
+        ),
+        max: Vec2::new(TILE_SIZE * (grid.width - 1) as f32 + half_tile, half_tile),
+    });
+    commands.insert_resource(grid);
+    commands.insert_resource(metadata);
+
     commands
         .spawn()
         .insert(Name::new("Map"))
@@ -134,10 +268,47 @@ fn load_map(mut commands: Commands, graphics: Res<GraphicsHandles>, path: &Path)
         .push_children(&tiles);
 }
 
-fn parse_comment(line: &str, exits: &mut VecDeque<Door>) {
+fn parse_comment(
+    line: &str,
+    exits: &mut VecDeque<Door>,
+    npcs: &mut Vec<NpcSpawn>,
+    metadata: &mut MapMetadata,
+) {
     let comment: String = line.chars().skip(1).collect();
     let words: Vec<&str> = comment.split(' ').collect();
 
+    if words[0] == "npc" {
+        npcs.push(parse_npc(&words[1..]));
+        return;
+    }
+    if words[0] == "encounters" {
+        metadata.encounter_mode = match words[1] {
+            "random" => EncounterMode::Random,
+            "visible" => EncounterMode::Visible,
+            mode => panic!("Bad comment formatting, unknown encounter mode {}", mode),
+        };
+        metadata.min_level = words[2]
+            .parse::<i64>()
+            .expect("Bad comment formatting, no min level");
+        metadata.max_level = words[3]
+            .parse::<i64>()
+            .expect("Bad comment formatting, no max level");
+        //Levels are rolled from this range mid game so it has to be caught here
+        if metadata.min_level > metadata.max_level {
+            panic!(
+                "Bad comment formatting, min level {} is above max level {}",
+                metadata.min_level, metadata.max_level
+            );
+        }
+        return;
+    }
+
+    if words[0] == "music" {
+        let path = words.get(1).expect("Bad comment formatting, no music path");
+        metadata.music = Some(path.trim().to_string());
+        return;
+    }
+
     let path = words[0];
     let x = words[1]
         .parse::<i32>()
@@ -176,7 +347,7 @@ fn parse_tile(
         .id();
 
     match c {
-        '#' | 'W' => {
+        _ if is_collider(c) => {
             commands.entity(tile_ent).insert(TileCollider);
         }
         'G' => {
@@ -195,6 +366,10 @@ fn parse_tile(
     tile_ent
 }
 
+fn is_collider(c: char) -> bool {
+    matches!(c, '#' | 'W')
+}
+
 fn sprite_lookup(c: char) -> TextureAtlasSprite {
     let mut tile = TextureAtlasSprite::new(match c {
         '#' => 0,
@@ -0,0 +1,328 @@
+use bevy::prelude::*;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+use crate::ascii::TextWidget;
+
+pub struct TweenPlugin;
+
+impl Plugin for TweenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TweenCompleted>()
+            .add_system(run_tweens.label("tweens"));
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Ease {
+    Linear,
+    QuadIn,
+    QuadOut,
+    SineInOut,
+    //Overshoots the end slightly then settles, good for things sliding into place
+    BackOut,
+}
+
+impl Ease {
+    //Maps linear progress 0..1 to eased progress, 0 and 1 always map to themselves
+    pub fn apply(&self, t: f32) -> f32 {
+        match self {
+            Ease::Linear => t,
+            Ease::QuadIn => t * t,
+            Ease::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Ease::SineInOut => -((PI * t).cos() - 1.0) / 2.0,
+            Ease::BackOut => {
+                let c1 = 1.70158;
+                let c3 = c1 + 1.0;
+                1.0 + c3 * (t - 1.0).powi(3) + c1 * (t - 1.0).powi(2)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum TweenTarget {
+    Translation { start: Vec3, end: Vec3 },
+    Scale { start: Vec3, end: Vec3 },
+    Rotation { start: Quat, end: Quat },
+    //Color of the entity's TextureAtlasSprite
+    SpriteColor { start: Color, end: Color },
+    //Default color of the entity's TextWidget
+    TextColor { start: Color, end: Color },
+}
+
+fn lerp_color(start: Color, end: Color, t: f32) -> Color {
+    let start = Vec4::from(start.as_rgba_f32());
+    let end = Vec4::from(end.as_rgba_f32());
+    let color = start.lerp(end, t);
+    Color::rgba(color.x, color.y, color.z, color.w)
+}
+
+//One step of a Tweener, all targets play at the same time
+#[derive(Clone)]
+pub struct Tween {
+    targets: Vec<TweenTarget>,
+    timer: Timer,
+    ease: Ease,
+    //Sends a TweenCompleted with this name when the step finishes
+    event: Option<String>,
+}
+
+impl Tween {
+    pub fn new(target: TweenTarget, duration: f32, ease: Ease) -> Tween {
+        Tween {
+            targets: vec![target],
+            timer: Timer::from_seconds(duration, false),
+            ease: ease,
+            event: None,
+        }
+    }
+
+    //Does nothing for a while, useful for spacing out a sequence
+    pub fn delay(duration: f32) -> Tween {
+        Tween {
+            targets: Vec::new(),
+            timer: Timer::from_seconds(duration, false),
+            ease: Ease::Linear,
+            event: None,
+        }
+    }
+
+    //Plays another target alongside this one with the same timing
+    pub fn and(mut self, target: TweenTarget) -> Tween {
+        self.targets.push(target);
+        self
+    }
+
+    pub fn with_event(mut self, name: &str) -> Tween {
+        self.event = Some(name.to_string());
+        self
+    }
+}
+
+pub struct TweenCompleted {
+    pub entity: Entity,
+    pub name: String,
+}
+
+//Plays tweens one after another, removes itself once the last one finishes
+#[derive(Component)]
+pub struct Tweener {
+    sequence: VecDeque<Tween>,
+}
+
+impl Tweener {
+    pub fn new(tween: Tween) -> Tweener {
+        Tweener {
+            sequence: VecDeque::from(vec![tween]),
+        }
+    }
+
+    pub fn then(mut self, tween: Tween) -> Tweener {
+        self.sequence.push_back(tween);
+        self
+    }
+}
+
+fn apply_tween(
+    tween: &Tween,
+    transform: &mut Option<Mut<Transform>>,
+    sprite: &mut Option<Mut<TextureAtlasSprite>>,
+    text: &mut Option<Mut<TextWidget>>,
+) {
+    let t = tween.ease.apply(tween.timer.percent());
+    for target in tween.targets.iter() {
+        match *target {
+            TweenTarget::Translation { start, end } => {
+                if let Some(transform) = transform {
+                    transform.translation = start.lerp(end, t);
+                }
+            }
+            TweenTarget::Scale { start, end } => {
+                if let Some(transform) = transform {
+                    transform.scale = start.lerp(end, t);
+                }
+            }
+            TweenTarget::Rotation { start, end } => {
+                if let Some(transform) = transform {
+                    transform.rotation = start.slerp(end, t);
+                }
+            }
+            TweenTarget::SpriteColor { start, end } => {
+                if let Some(sprite) = sprite {
+                    sprite.color = lerp_color(start, end, t);
+                }
+            }
+            TweenTarget::TextColor { start, end } => {
+                if let Some(text) = text {
+                    text.color = lerp_color(start, end, t);
+                }
+            }
+        }
+    }
+}
+
+fn run_tweens(
+    mut commands: Commands,
+    mut tween_query: Query<(
+        Entity,
+        &mut Tweener,
+        Option<&mut Transform>,
+        Option<&mut TextureAtlasSprite>,
+        Option<&mut TextWidget>,
+    )>,
+    time: Res<Time>,
+    mut completed: EventWriter<TweenCompleted>,
+) {
+    for (entity, mut tweener, mut transform, mut sprite, mut text) in tween_query.iter_mut() {
+        let mut delta = time.delta();
+        //Leftover time carries into the next step so sequences don't drift
+        while let Some(tween) = tweener.sequence.front_mut() {
+            let remaining = tween.timer.duration() - tween.timer.elapsed();
+            tween.timer.tick(delta);
+            apply_tween(tween, &mut transform, &mut sprite, &mut text);
+            if !tween.timer.finished() {
+                break;
+            }
+            delta = delta.saturating_sub(remaining);
+
+            let tween = tweener.sequence.pop_front().unwrap();
+            if let Some(name) = tween.event {
+                completed.send(TweenCompleted {
+                    entity: entity,
+                    name: name,
+                });
+            }
+        }
+
+        if tweener.sequence.is_empty() {
+            commands.entity(entity).remove::<Tweener>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::app::Events;
+    use std::time::Duration;
+
+    const ALL_EASES: [Ease; 5] = [
+        Ease::Linear,
+        Ease::QuadIn,
+        Ease::QuadOut,
+        Ease::SineInOut,
+        Ease::BackOut,
+    ];
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn eases_keep_their_ends() {
+        for ease in ALL_EASES {
+            assert!(close(ease.apply(0.0), 0.0));
+            assert!(close(ease.apply(1.0), 1.0));
+        }
+    }
+
+    #[test]
+    fn eases_at_halfway() {
+        assert!(close(Ease::Linear.apply(0.5), 0.5));
+        assert!(close(Ease::QuadIn.apply(0.5), 0.25));
+        assert!(close(Ease::QuadOut.apply(0.5), 0.75));
+        assert!(close(Ease::SineInOut.apply(0.5), 0.5));
+    }
+
+    #[test]
+    fn eases_only_move_forwards_except_back_out() {
+        for ease in [Ease::Linear, Ease::QuadIn, Ease::QuadOut, Ease::SineInOut] {
+            let mut last = ease.apply(0.0);
+            for step in 1..=100 {
+                let value = ease.apply(step as f32 / 100.0);
+                assert!(value >= last);
+                assert!(value <= 1.0 + 1e-5);
+                last = value;
+            }
+        }
+    }
+
+    //Real time has to pass for Time to report a delta
+    fn run_tweens_after(world: &mut World, wait: Duration) {
+        let mut time = Time::default();
+        time.update();
+        std::thread::sleep(wait);
+        time.update();
+        world.insert_resource(time);
+        SystemStage::single(run_tweens).run(world);
+    }
+
+    fn world_with_events() -> World {
+        let mut world = World::new();
+        world.insert_resource(Events::<TweenCompleted>::default());
+        world
+    }
+
+    #[test]
+    fn finished_tweeners_write_the_end_and_remove_themselves() {
+        let mut world = world_with_events();
+        let end = Vec3::new(1.0, 2.0, 0.0);
+        let entity = world
+            .spawn()
+            .insert(Transform::default())
+            .insert(Tweener::new(
+                Tween::new(
+                    TweenTarget::Translation {
+                        start: Vec3::ZERO,
+                        end: end,
+                    },
+                    0.01,
+                    Ease::QuadIn,
+                )
+                .with_event("done"),
+            ))
+            .id();
+
+        run_tweens_after(&mut world, Duration::from_millis(20));
+
+        assert_eq!(world.get::<Transform>(entity).unwrap().translation, end);
+        assert!(world.get::<Tweener>(entity).is_none());
+        let events = world.get_resource::<Events<TweenCompleted>>().unwrap();
+        let sent: Vec<&TweenCompleted> = events.get_reader().iter(events).collect();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].entity, entity);
+        assert_eq!(sent[0].name, "done");
+    }
+
+    #[test]
+    fn leftover_time_moves_on_to_the_next_tween() {
+        let mut world = world_with_events();
+        let entity = world
+            .spawn()
+            .insert(Transform::default())
+            .insert(Tweener::new(Tween::delay(0.01)).then(Tween::new(
+                TweenTarget::Scale {
+                    start: Vec3::ONE,
+                    end: Vec3::splat(2.0),
+                },
+                1000.0,
+                Ease::Linear,
+            )))
+            .id();
+
+        run_tweens_after(&mut world, Duration::from_millis(20));
+
+        let scale = world.get::<Transform>(entity).unwrap().scale.x;
+        assert!(scale > 1.0 && scale < 2.0);
+        assert!(world.get::<Tweener>(entity).is_some());
+    }
+
+    #[test]
+    fn back_out_overshoots() {
+        let peak = (1..100)
+            .map(|step| Ease::BackOut.apply(step as f32 / 100.0))
+            .fold(0.0, f32::max);
+        assert!(peak > 1.0);
+    }
+}
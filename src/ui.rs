@@ -0,0 +1,363 @@
+use bevy::prelude::*;
+use bevy_kira_audio::Audio;
+
+use crate::ascii::{
+    spawn_ascii_sprite, spawn_ascii_text, spawn_ascii_text_with_layout, AsciiSheet, TextAlign,
+    TextLayout, TextWidget,
+};
+use crate::audio::AudioState;
+use crate::input::GameAction;
+use crate::nine_sprite::{spawn_nine_sprite, NineSprite, NineSpriteBorder, NineSpriteTheme};
+use crate::TILE_SIZE;
+
+const SELECTED_COLOR: Color = Color::RED;
+//Triangles on the ascii sheet
+const MORE_ABOVE_INDEX: usize = 30;
+const MORE_BELOW_INDEX: usize = 31;
+
+pub struct UiPlugin;
+
+impl Plugin for UiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ListConfirmed>()
+            .add_system(navigate_lists.label("ui_navigation"))
+            .add_system(navigate_scroll_lists.label("ui_navigation"))
+            .add_system(highlight_selected.after("ui_navigation"))
+            .add_system(update_scroll_lists.after("ui_navigation"));
+    }
+}
+
+//Only focused lists take input, move this between lists to change which one is in use
+#[derive(Component)]
+pub struct Focused;
+
+//Sent when Confirm is pressed on a focused list
+pub struct ListConfirmed {
+    pub list: Entity,
+    pub index: usize,
+}
+
+//Items laid out left to right then top to bottom, one column makes a vertical list
+#[derive(Component)]
+pub struct UiList {
+    pub selected: usize,
+    columns: usize,
+    items: Vec<Entity>,
+}
+
+//Vertical list of text that only shows a few rows at a time
+//Arrows on the right show when there's more above or below
+#[derive(Component)]
+pub struct ScrollList {
+    pub items: Vec<String>,
+    pub selected: usize,
+    //First item on screen
+    scroll: usize,
+    rows: Vec<Entity>,
+    more_above: Entity,
+    more_below: Entity,
+}
+
+impl ScrollList {
+    fn scroll_to_selected(&mut self) {
+        let visible = self.rows.len();
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + visible {
+            self.scroll = self.selected + 1 - visible;
+        }
+    }
+}
+
+//A panel with a label in the middle, the frame lights up when selected in a list
+#[derive(Component)]
+pub struct UiButton {
+    frame: Entity,
+}
+
+//Empty box to put other widgets in, children should be added relative to its center
+pub fn spawn_panel(
+    commands: &mut Commands,
+    ascii: AsciiSheet,
+    theme: NineSpriteTheme,
+    size: Vec2,
+    translation: Vec3,
+) -> Entity {
+    let frame = spawn_nine_sprite(commands, ascii, theme, size.x, size.y);
+    commands
+        .spawn()
+        .insert(Name::new("Panel"))
+        //Needs transforms for parent heirarchy system to work
+        .insert(Transform::from_translation(translation))
+        .insert(GlobalTransform::default())
+        .push_children(&[frame])
+        .id()
+}
+
+//Text centered on its position
+pub fn spawn_label(commands: &mut Commands, text: &str, translation: Vec3) -> Entity {
+    let layout = TextLayout {
+        align: TextAlign::Center,
+        ..Default::default()
+    };
+    spawn_ascii_text_with_layout(commands, text, translation, layout)
+}
+
+pub fn spawn_button(
+    commands: &mut Commands,
+    ascii: AsciiSheet,
+    theme: NineSpriteTheme,
+    text: &str,
+    size: Vec2,
+) -> Entity {
+    let frame = spawn_nine_sprite(commands, ascii, theme, size.x, size.y);
+    let label = spawn_label(commands, text, Vec3::ZERO);
+    commands
+        .spawn()
+        .insert(Name::new(text.to_owned() + "Button"))
+        .insert(UiButton { frame: frame })
+        .insert(Transform::default())
+        .insert(GlobalTransform::default())
+        .push_children(&[frame, label])
+        .id()
+}
+
+//Lays the items out in a grid of cells centered on translation and takes them as children
+pub fn spawn_list(
+    commands: &mut Commands,
+    items: &[Entity],
+    columns: usize,
+    cell_size: Vec2,
+    translation: Vec3,
+) -> Entity {
+    let columns = columns.max(1);
+    let rows = (items.len() + columns - 1) / columns;
+    let top_left = Vec2::new(
+        -(columns as f32 - 1.0) / 2.0 * cell_size.x,
+        (rows as f32 - 1.0) / 2.0 * cell_size.y,
+    );
+    for (i, item) in items.iter().enumerate() {
+        let cell = Vec2::new((i % columns) as f32, -((i / columns) as f32));
+        let position = top_left + cell * cell_size;
+        commands
+            .entity(*item)
+            .insert(Transform::from_translation(position.extend(0.0)));
+    }
+
+    commands
+        .spawn()
+        .insert(Name::new("List"))
+        .insert(UiList {
+            selected: 0,
+            columns: columns,
+            items: items.to_vec(),
+        })
+        .insert(Transform::from_translation(translation))
+        .insert(GlobalTransform::default())
+        .push_children(items)
+        .id()
+}
+
+//Width includes the border, height fits visible_rows plus the border
+pub fn spawn_scroll_list(
+    commands: &mut Commands,
+    ascii: AsciiSheet,
+    theme: NineSpriteTheme,
+    items: Vec<String>,
+    visible_rows: usize,
+    width: f32,
+    translation: Vec3,
+) -> Entity {
+    let visible_rows = visible_rows.max(1);
+    let height = (visible_rows as f32 + 2.0) * TILE_SIZE;
+    let top = height / 2.0 - 1.5 * TILE_SIZE;
+    let mut children = vec![spawn_nine_sprite(
+        commands,
+        ascii.clone(),
+        theme,
+        width,
+        height,
+    )];
+
+    //Filled in by update_scroll_lists
+    let rows: Vec<Entity> = (0..visible_rows)
+        .map(|i| {
+            let position = Vec3::new(-width / 2.0 + TILE_SIZE, top - i as f32 * TILE_SIZE, 0.0);
+            spawn_ascii_text(commands, "", position)
+        })
+        .collect();
+    children.extend(rows.iter());
+
+    let arrow_x = width / 2.0 - 1.5 * TILE_SIZE;
+    let bottom = top - (visible_rows as f32 - 1.0) * TILE_SIZE;
+    let more_above = spawn_ascii_sprite(
+        commands,
+        &ascii,
+        MORE_ABOVE_INDEX,
+        Color::WHITE,
+        Vec3::new(arrow_x, top, 0.0),
+        Vec3::splat(1.0),
+    );
+    let more_below = spawn_ascii_sprite(
+        commands,
+        &ascii,
+        MORE_BELOW_INDEX,
+        Color::WHITE,
+        Vec3::new(arrow_x, bottom, 0.0),
+        Vec3::splat(1.0),
+    );
+    children.extend([more_above, more_below]);
+
+    commands
+        .spawn()
+        .insert(Name::new("ScrollList"))
+        .insert(ScrollList {
+            items: items,
+            selected: 0,
+            scroll: 0,
+            rows: rows,
+            more_above: more_above,
+            more_below: more_below,
+        })
+        .insert(Transform::from_translation(translation))
+        .insert(GlobalTransform::default())
+        .push_children(&children)
+        .id()
+}
+
+//Left and right step through items, up and down jump a whole row, both wrap around
+fn navigate_lists(
+    mut list_query: Query<(Entity, &mut UiList), With<Focused>>,
+    actions: Res<Input<GameAction>>,
+    mut confirmed: EventWriter<ListConfirmed>,
+    mut audio_state: ResMut<AudioState>,
+    audio: Res<Audio>,
+) {
+    for (entity, mut list) in list_query.iter_mut() {
+        let len = list.items.len();
+        if len == 0 {
+            continue;
+        }
+        if actions.just_pressed(GameAction::Confirm) {
+            confirmed.send(ListConfirmed {
+                list: entity,
+                index: list.selected,
+            });
+            audio_state.play(&audio, "confirm");
+            continue;
+        }
+
+        let mut step = 0;
+        if actions.just_pressed(GameAction::MoveRight) {
+            step += 1;
+        }
+        if actions.just_pressed(GameAction::MoveLeft) {
+            step -= 1;
+        }
+        if actions.just_pressed(GameAction::MoveDown) {
+            step += list.columns as isize;
+        }
+        if actions.just_pressed(GameAction::MoveUp) {
+            step -= list.columns as isize;
+        }
+        if step != 0 {
+            list.selected = (list.selected as isize + step).rem_euclid(len as isize) as usize;
+            audio_state.play(&audio, "cursor");
+        }
+    }
+}
+
+//Up and down move one row and wrap around, the view scrolls to keep the selection on screen
+fn navigate_scroll_lists(
+    mut list_query: Query<(Entity, &mut ScrollList), With<Focused>>,
+    actions: Res<Input<GameAction>>,
+    mut confirmed: EventWriter<ListConfirmed>,
+    mut audio_state: ResMut<AudioState>,
+    audio: Res<Audio>,
+) {
+    for (entity, mut list) in list_query.iter_mut() {
+        let len = list.items.len();
+        if len == 0 {
+            continue;
+        }
+        if actions.just_pressed(GameAction::Confirm) {
+            confirmed.send(ListConfirmed {
+                list: entity,
+                index: list.selected.min(len - 1),
+            });
+            audio_state.play(&audio, "confirm");
+            continue;
+        }
+
+        let step = isize::from(actions.just_pressed(GameAction::MoveDown))
+            - isize::from(actions.just_pressed(GameAction::MoveUp));
+        if step != 0 {
+            list.selected = (list.selected as isize + step).rem_euclid(len as isize) as usize;
+            list.scroll_to_selected();
+            audio_state.play(&audio, "cursor");
+        }
+    }
+}
+
+fn update_scroll_lists(
+    list_query: Query<&ScrollList, Changed<ScrollList>>,
+    mut text_query: Query<&mut TextWidget>,
+    mut visibility_query: Query<&mut Visibility>,
+) {
+    for list in list_query.iter() {
+        let visible = list.rows.len();
+        //Items might have been swapped out for fewer since the last scroll
+        let scroll = list.scroll.min(list.items.len().saturating_sub(visible));
+        for (i, row) in list.rows.iter().enumerate() {
+            let text = match list.items.get(scroll + i) {
+                //Item names are plain text, not markup
+                Some(item) if scroll + i == list.selected => {
+                    format!("{{red}}> {}{{/}}", item.replace('{', "{{"))
+                }
+                Some(item) => format!("  {}", item.replace('{', "{{")),
+                None => String::new(),
+            };
+            if let Ok(mut widget) = text_query.get_mut(*row) {
+                if widget.text != text {
+                    widget.text = text;
+                }
+            }
+        }
+
+        if let Ok(mut arrow) = visibility_query.get_mut(list.more_above) {
+            arrow.is_visible = scroll > 0;
+        }
+        if let Ok(mut arrow) = visibility_query.get_mut(list.more_below) {
+            arrow.is_visible = scroll + visible < list.items.len();
+        }
+    }
+}
+
+//Unselected buttons go back to their theme's border colour
+fn highlight_selected(
+    list_query: Query<&UiList, Changed<UiList>>,
+    button_query: Query<&UiButton>,
+    frame_query: Query<(&NineSprite, &Children)>,
+    mut border_query: Query<&mut TextureAtlasSprite, With<NineSpriteBorder>>,
+) {
+    for list in list_query.iter() {
+        for (i, item) in list.items.iter().enumerate() {
+            let button = match button_query.get(*item) {
+                Ok(button) => button,
+                Err(_) => continue,
+            };
+            if let Ok((frame, children)) = frame_query.get(button.frame) {
+                let color = if i == list.selected {
+                    SELECTED_COLOR
+                } else {
+                    frame.border_color
+                };
+                for child in children.iter() {
+                    if let Ok(mut sprite) = border_query.get_mut(*child) {
+                        sprite.color = color;
+                    }
+                }
+            }
+        }
+    }
+}
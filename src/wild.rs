@@ -0,0 +1,163 @@
+use bevy::prelude::*;
+use bevy::sprite::collide_aabb::collide;
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng};
+
+use crate::ascii::{spawn_ascii_sprite, AsciiSheet};
+use crate::enemy::{random_enemy_type, Enemy, EnemyType};
+use crate::graphics::FacingDirection;
+use crate::input::InputLock;
+use crate::npc::{Npc, NpcBehavior};
+use crate::player::{CombatEvent, Player};
+use crate::screen_fadeout::{create_fadeout, TransitionStyles, TRANSITION_LOCK};
+use crate::tilemap::{grid_to_world, EncounterMode, Map, MapGrid, MapMetadata};
+use crate::{GameState, TILE_SIZE};
+
+const MAX_WILD_MONSTERS: usize = 30;
+//Roughly one monster per this many grass tiles
+const TILES_PER_MONSTER: usize = 12;
+
+#[derive(Component)]
+pub struct WildMonster {
+    enemy_type: EnemyType,
+    level: i64,
+    //Started after running away so the fight doesn't restart instantly
+    cooldown: Timer,
+}
+
+//The overworld monster currently being fought
+#[derive(Component)]
+pub struct Engaged;
+
+pub struct WildMonsterPlugin;
+
+impl Plugin for WildMonsterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_wild_monsters)
+            .add_system_set(
+                SystemSet::on_update(GameState::Overworld)
+                    .with_system(tick_cooldowns)
+                    //Movement runs in the fixed stage before this
+                    .with_system(wild_monster_collision),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Overworld).with_system(release_engaged_monsters),
+            );
+    }
+}
+
+fn spawn_wild_monsters(
+    mut commands: Commands,
+    map_query: Query<Entity, Added<Map>>,
+    //Runs in every state, including before the first map is loaded
+    grid: Option<Res<MapGrid>>,
+    metadata: Option<Res<MapMetadata>>,
+    ascii: Res<AsciiSheet>,
+) {
+    let (grid, metadata) = match (grid, metadata) {
+        (Some(grid), Some(metadata)) => (grid, metadata),
+        _ => return,
+    };
+    for map in map_query.iter() {
+        if metadata.encounter_mode != EncounterMode::Visible {
+            continue;
+        }
+
+        let mut rng = thread_rng();
+        let wild_tiles: Vec<IVec2> = grid.wild_tiles().copied().collect();
+        let count = (wild_tiles.len() / TILES_PER_MONSTER + 1).min(MAX_WILD_MONSTERS);
+        let spawn_tiles: Vec<IVec2> = wild_tiles
+            .choose_multiple(&mut rng, count)
+            .copied()
+            .collect();
+
+        let mut monsters = Vec::new();
+        for tile in spawn_tiles {
+            let enemy_type = random_enemy_type();
+            let enemy = Enemy::new(
+                enemy_type,
+                rng.gen_range(metadata.min_level..=metadata.max_level),
+            );
+            let monster = spawn_ascii_sprite(
+                &mut commands,
+                &ascii,
+                enemy.sprite_index,
+                enemy.color,
+                //Above the map but under the player
+                grid_to_world(tile, 800.0),
+                Vec3::splat(1.0),
+            );
+            commands
+                .entity(monster)
+                .insert(Name::new("WildMonster"))
+                .insert(WildMonster {
+                    enemy_type: enemy_type,
+                    level: enemy.level,
+                    cooldown: Timer::from_seconds(0.0, false),
+                })
+                .insert(Npc::new(NpcBehavior::Roam, 1.5))
+                .insert(FacingDirection::Down);
+            monsters.push(monster);
+        }
+        commands.entity(map).push_children(&monsters);
+    }
+}
+
+fn tick_cooldowns(mut monster_query: Query<&mut WildMonster>, time: Res<Time>) {
+    for mut monster in monster_query.iter_mut() {
+        monster.cooldown.tick(time.delta());
+    }
+}
+
+fn wild_monster_collision(
+    mut commands: Commands,
+    player_query: Query<(&Player, &Transform)>,
+    monster_query: Query<(Entity, &WildMonster, &Transform), Without<Player>>,
+    ascii: Res<AsciiSheet>,
+    styles: Res<TransitionStyles>,
+    mut input_lock: ResMut<InputLock>,
+) {
+    let (player, player_transform) = player_query.single();
+    if input_lock.is_locked() {
+        return;
+    }
+
+    for (entity, monster, transform) in monster_query.iter() {
+        if !monster.cooldown.finished() {
+            continue;
+        }
+        let collision = collide(
+            player_transform.translation,
+            player.hitbox(),
+            transform.translation,
+            Vec2::splat(TILE_SIZE),
+        );
+        if collision.is_some() {
+            input_lock.set(TRANSITION_LOCK, true);
+            commands.entity(entity).insert(Engaged);
+            let event = CombatEvent {
+                enemy_type: monster.enemy_type,
+                level: monster.level,
+            };
+            create_fadeout(
+                &mut commands,
+                ascii.clone(),
+                event,
+                0.8,
+                styles.wild_encounter,
+            );
+            return;
+        }
+    }
+}
+
+//Monsters that were beaten are already despawned, anything left was run from
+fn release_engaged_monsters(
+    mut commands: Commands,
+    mut monster_query: Query<(Entity, &mut WildMonster), With<Engaged>>,
+) {
+    for (entity, mut monster) in monster_query.iter_mut() {
+        commands.entity(entity).remove::<Engaged>();
+        monster.cooldown = Timer::from_seconds(2.0, false);
+    }
+}